@@ -0,0 +1,313 @@
+use structopt::StructOpt;
+use serde::Serialize;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+
+// infer tile adjacency rules from collapsed tile maps
+//
+// this records which tiles are actually seen next to each other in each
+// direction, and writes them out as a tile set, or as a diff against our
+// handwritten constraints, to help tighten or loosen rules from real data
+//
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name="adjacency",
+    about="Count which tiles are seen next to each other in real maps.",
+    rename_all="kebab"
+)]
+pub struct AdjacencyOpt {
+    /// Tile maps to learn from, either json output or ascii tile maps.
+    ///
+    /// Several tiles may share a glyph, so ascii tile maps are resolved to
+    /// whichever tiles best fit their neighbors first, without enforcing
+    /// our constraints. Json output has exact tiles.
+    #[structopt(required=true)]
+    maps: Vec<PathBuf>,
+
+    /// Write a diff against our current constraints instead of a tile set.
+    ///
+    /// Lines starting with + were observed but aren't allowed, and lines
+    /// starting with - are allowed but were never observed.
+    #[structopt(long)]
+    diff: bool,
+
+    /// File to write to, defaults to stdout.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+}
+
+const DIRS: [(Dir, &str, isize, isize); 4] = [
+    (Dir::N, "n", 0, -1),
+    (Dir::E, "e", 1, 0),
+    (Dir::S, "s", 0, 1),
+    (Dir::W, "w", -1, 0),
+];
+
+// a tile map of tile indices
+struct Tiles {
+    width: usize,
+    height: usize,
+    tiles: Vec<usize>,
+}
+
+// find our neighbor in a direction, if it's in our map
+fn neighbor(
+    width: usize,
+    height: usize,
+    i: usize,
+    d: usize,
+) -> Option<usize> {
+    let (_, _, d_x, d_y) = DIRS[d];
+    let x = (i % width) as isize + d_x;
+    let y = (i / width) as isize + d_y;
+    (x >= 0 && x < width as isize && y >= 0 && y < height as isize)
+        .then(|| x as usize + y as usize*width)
+}
+
+// resolve any glyphs shared by several tiles, without enforcing our
+// constraints, hand-edited maps may break our rules on purpose
+//
+// this narrows each cell to what its neighbors allow, unless that would
+// leave nothing, and then picks whichever tile fits the most neighbors in
+// reading order, narrowing as we go
+fn resolve_relaxed(width: usize, height: usize, cmap: &mut [u128]) {
+    let supports = tile_supports();
+    let fits = |cmap: &[u128], i: usize, t: usize| {
+        (0..4)
+            .filter(|&d| {
+                neighbor(width, height, i, d)
+                    .is_some_and(|j| cmap[j] & supports[t][d] != 0)
+            })
+            .count()
+    };
+    let narrow = |cmap: &mut [u128], mut pending: Vec<usize>| {
+        while let Some(i) = pending.pop() {
+            let neighbors = (0..4)
+                .filter(|&d| neighbor(width, height, i, d).is_some())
+                .count();
+            let c = (0..TILES.len())
+                .filter(|&t| cmap[i] & (1 << t) != 0)
+                .filter(|&t| fits(cmap, i, t) == neighbors)
+                .fold(0, |c, t| c | (1 << t));
+            if c != 0 && c != cmap[i] {
+                cmap[i] = c;
+                pending.extend(
+                    (0..4).filter_map(|d| neighbor(width, height, i, d))
+                );
+            }
+        }
+    };
+
+    narrow(cmap, (0..cmap.len()).collect());
+    for i in 0..cmap.len() {
+        if cmap[i].count_ones() <= 1 {
+            continue;
+        }
+
+        // note max_by_key prefers the last max, we prefer the first
+        let t = (0..TILES.len())
+            .filter(|&t| cmap[i] & (1 << t) != 0)
+            .rev()
+            .max_by_key(|&t| fits(cmap, i, t))
+            .unwrap();
+        cmap[i] = 1 << t;
+        narrow(
+            cmap,
+            (0..4).filter_map(|d| neighbor(width, height, i, d)).collect(),
+        );
+    }
+}
+
+fn invalid(path: &Path, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), msg),
+    )
+}
+
+// read any tile maps in a file, json output may contain several decks
+fn read_tiles(path: &Path) -> io::Result<Vec<Tiles>> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let json: serde_json::Value = serde_json::from_reader(
+            io::BufReader::new(File::open(path)?)
+        )?;
+        let decks = match json.get("decks").and_then(|decks| decks.as_array()) {
+            Some(decks) => decks.clone(),
+            None => vec![json],
+        };
+
+        let mut maps = vec![];
+        for deck in decks {
+            let tiles = match deck.get("tiles") {
+                Some(tiles) if !tiles.is_null() => tiles,
+                _ => return Err(invalid(path, "no tiles, was this run \
+                    with wave-function collapse?")),
+            };
+            let dim = |field: &str| {
+                tiles.get(field)
+                    .and_then(|dim| dim.as_u64())
+                    .map(|dim| dim as usize)
+                    .ok_or_else(|| invalid(path, &format!("no {}", field)))
+            };
+            let width = dim("width")?;
+            let height = dim("height")?;
+            let tiles = tiles.get("tiles")
+                .and_then(|tiles| tiles.as_array())
+                .ok_or_else(|| invalid(path, "no tiles"))?
+                .iter()
+                .map(|tile| {
+                    tile.as_u64()
+                        .map(|tile| tile as usize)
+                        .filter(|&tile| tile < TILES.len())
+                        .ok_or_else(|| invalid(
+                            path,
+                            &format!("unknown tile {}", tile),
+                        ))
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            if tiles.len() != width*height {
+                return Err(invalid(path, "tiles don't match width/height"));
+            }
+
+            maps.push(Tiles{width, height, tiles});
+        }
+        Ok(maps)
+    } else {
+        let ascii = fs::read_to_string(path)?;
+        let (width, height, mut cmap) = parse_tile_map(&ascii, 0)
+            .map_err(|e| invalid(path, &e))?;
+        resolve_relaxed(width, height, &mut cmap);
+        let tiles = cmap.iter()
+            .map(|c| c.trailing_zeros() as usize)
+            .collect::<Vec<_>>();
+
+        // report anything that breaks our constraints, these are what
+        // --diff shows as + lines, note we only need to look e and s to
+        // see every pair
+        let supports = tile_supports();
+        for i in 0..tiles.len() {
+            for d in [1, 2] {
+                let (_, name, _, _) = DIRS[d];
+                if let Some(j) = neighbor(width, height, i, d) {
+                    if supports[tiles[i]][d] & (1 << tiles[j]) == 0 {
+                        eprintln!("{}:{}:{}: {} {} {} isn't allowed",
+                            path.display(),
+                            i/width+1, 2*(i%width)+1,
+                            TILES[tiles[i]].name, name, TILES[tiles[j]].name
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(vec![Tiles{width, height, tiles}])
+    }
+}
+
+pub fn adjacency(adjacency: AdjacencyOpt) -> io::Result<()> {
+    // count how often each tile is seen, and how often each tile is seen
+    // next to each other tile in each direction
+    let mut seen = vec![0usize; TILES.len()];
+    let mut pairs = vec![0usize; TILES.len()*4*TILES.len()];
+    let pair = |a: usize, d: usize, b: usize| (a*4 + d)*TILES.len() + b;
+    let mut count = 0;
+    for path in &adjacency.maps {
+        for map in read_tiles(path)? {
+            count += 1;
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    let a = map.tiles[x+y*map.width];
+                    seen[a] += 1;
+                    for (d, &(_, _, d_x, d_y)) in DIRS.iter().enumerate() {
+                        let x_ = x as isize + d_x;
+                        let y_ = y as isize + d_y;
+                        if
+                            x_ >= 0 && x_ < map.width as isize
+                                && y_ >= 0 && y_ < map.height as isize
+                        {
+                            let b = map.tiles[
+                                x_ as usize + y_ as usize*map.width
+                            ];
+                            pairs[pair(a, d, b)] += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out: Box<dyn Write> = match &adjacency.output {
+        Some(output) => Box::new(io::BufWriter::new(File::create(output)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    if adjacency.diff {
+        // note we only diff tiles we've actually seen, tiles we haven't
+        // seen don't tell us anything
+        writeln!(out, "# {} maps, {} tiles",
+            count,
+            seen.iter().sum::<usize>(),
+        )?;
+        for a in 0..TILES.len() {
+            if seen[a] == 0 {
+                continue;
+            }
+            for (d, &(dir, name, _, _)) in DIRS.iter().enumerate() {
+                let allowed = TILES[a].constraints.dir(dir);
+                for b in 0..TILES.len() {
+                    let observed = pairs[pair(a, d, b)];
+                    if observed > 0 && allowed & (1 << b) == 0 {
+                        writeln!(out, "+ {} {} {} {}",
+                            TILES[a].name, name, TILES[b].name, observed
+                        )?;
+                    } else if observed == 0 && allowed & (1 << b) != 0 {
+                        writeln!(out, "- {} {} {}",
+                            TILES[a].name, name, TILES[b].name
+                        )?;
+                    }
+                }
+            }
+        }
+    } else {
+        #[derive(Serialize)]
+        struct SerializeTile {
+            name: &'static str,
+            count: usize,
+            n: Vec<&'static str>,
+            e: Vec<&'static str>,
+            s: Vec<&'static str>,
+            w: Vec<&'static str>,
+        }
+
+        let observed = |a: usize, d: usize| {
+            (0..TILES.len())
+                .filter(|&b| pairs[pair(a, d, b)] > 0)
+                .map(|b| TILES[b].name)
+                .collect::<Vec<_>>()
+        };
+        let tiles = (0..TILES.len())
+            .map(|a| SerializeTile{
+                name: TILES[a].name,
+                count: seen[a],
+                n: observed(a, 0),
+                e: observed(a, 1),
+                s: observed(a, 2),
+                w: observed(a, 3),
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut out, &tiles)?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+
+    if let Some(output) = &adjacency.output {
+        println!("read {} maps, {} tiles", count, seen.iter().sum::<usize>());
+        println!("updated {:?}", output);
+    }
+    Ok(())
+}
@@ -1,11 +1,181 @@
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::str;
 use std::mem;
-use std::io::{self, Write};
+use std::cmp;
+use std::io::{self, Write, BufWriter};
+use std::fs::File;
+use std::path::Path;
 
+use serde_json::json;
+
+use crate::raster::GifRecorder;
+
+
+// query the terminal size in columns/rows, if stdout is a terminal
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut winsize = unsafe { mem::zeroed::<libc::winsize>() };
+    let res = unsafe {
+        libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize)
+    };
+    if res < 0 || winsize.ws_col == 0 || winsize.ws_row == 0 {
+        return None;
+    }
+
+    Some((winsize.ws_col as usize, winsize.ws_row as usize))
+}
+
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+// start watching for terminal resizes with SIGWINCH
+//
+// note we don't restart interrupted syscalls, so blocking reads return
+// early and can notice the resize
+//
+pub fn watch_resize() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| unsafe {
+        let mut action = mem::zeroed::<libc::sigaction>();
+        action.sa_sigaction = on_sigwinch as *const () as usize;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut());
+    });
+}
+
+// has the terminal resized since we last checked?
+pub fn resized() -> bool {
+    RESIZED.swap(false, Ordering::Relaxed)
+}
+
+// find the region that changed between two frames, returning the
+// range of lines and columns
+fn changed(
+    previous: &str,
+    frame: &[&str],
+) -> Option<((usize, usize), (usize, usize))> {
+    let previous = previous.lines().collect::<Vec<_>>();
+    let mut region: Option<((usize, usize), (usize, usize))> = None;
+    for (y, line) in frame.iter().enumerate() {
+        let before = previous.get(y).copied().unwrap_or("");
+        if *line == before {
+            continue;
+        }
+
+        // find the first/last differing columns
+        let a = line.chars().collect::<Vec<_>>();
+        let b = before.chars().collect::<Vec<_>>();
+        let lo = a.iter().zip(&b).take_while(|(a, b)| a == b).count();
+        let hi = cmp::max(a.len(), b.len())
+            - a.iter().rev().zip(b.iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+        let hi = cmp::max(hi, lo);
+
+        region = Some(match region {
+            None => ((y, y), (lo, hi)),
+            Some(((lo_y, _), (lo_x, hi_x))) => (
+                (lo_y, y),
+                (cmp::min(lo_x, lo), cmp::max(hi_x, hi)),
+            ),
+        });
+    }
+    region
+}
+
+
+// records frames as an asciinema v2 cast
+//
+// we only know the final width/height once we're done, so events are
+// buffered until then, to keep this small each event only redraws the
+// lines that changed
+//
+#[derive(Debug)]
+struct Recorder {
+    file: File,
+    start: Instant,
+    timestamp: u64,
+    width: usize,
+    height: usize,
+    previous: Vec<String>,
+    events: Vec<(f64, String)>,
+}
+
+impl Recorder {
+    fn new(path: &Path) -> io::Result<Self> {
+        Ok(Self{
+            file: File::create(path)?,
+            start: Instant::now(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            width: 1,
+            height: 1,
+            previous: vec![],
+            events: vec![],
+        })
+    }
+
+    fn frame(&mut self, frame: &[u8]) {
+        let time = self.start.elapsed().as_secs_f64();
+        let frame = str::from_utf8(frame).unwrap()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect::<Vec<_>>();
+
+        let mut data = String::new();
+        for (y, line) in frame.iter().enumerate() {
+            if self.previous.get(y) != Some(line) {
+                // \x1b[y;xH => move cursor to y,x
+                // \x1b[K    => clear line
+                data.push_str(&format!("\x1b[{};1H{}\x1b[K", y+1, line));
+            }
+        }
+        if frame.len() < self.previous.len() {
+            // \x1b[J => clear to end of screen
+            data.push_str(&format!("\x1b[{};1H\x1b[J", frame.len()+1));
+        }
+
+        self.width = cmp::max(
+            self.width,
+            frame.iter().map(|line| line.chars().count()).max().unwrap_or(0)
+        );
+        self.height = cmp::max(self.height, frame.len()+1);
+        self.previous = frame;
+        if !data.is_empty() {
+            self.events.push((time, data));
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut f = BufWriter::new(self.file);
+        writeln!(f, "{}", json!({
+            "version": 2,
+            "width": self.width,
+            "height": self.height,
+            "timestamp": self.timestamp,
+            "env": {"TERM": "xterm-256color"},
+        }))?;
+        // \x1b[H\x1b[2J => clear screen
+        writeln!(f, "{}", json!([0.0, "o", "\x1b[H\x1b[2J"]))?;
+        for (time, data) in &self.events {
+            writeln!(f, "{}", json!([time, "o", data]))?;
+        }
+        // leave the cursor below our frame
+        writeln!(f, "{}", json!([
+            self.events.last().map_or(0.0, |(time, _)| *time),
+            "o",
+            format!("\x1b[{};1H", self.height),
+        ]))?;
+        f.flush()
+    }
+}
 
 // a class for rendering things to the terminal in a background thread
 #[derive(Debug)]
@@ -13,6 +183,8 @@ pub struct BackgroundTerminal {
     foreground: Vec<u8>,
     shared: Arc<Mutex<(bool, Vec<u8>)>>,
     handle: Option<thread::JoinHandle<()>>,
+    recorder: Option<Recorder>,
+    gif: Option<GifRecorder>,
 }
 
 impl BackgroundTerminal {
@@ -28,49 +200,90 @@ impl BackgroundTerminal {
             let shared = Arc::clone(&shared);
             move || {
                 let mut background = vec![];
+                let mut previous = vec![];
                 let mut lines = 1;
+                let mut size = terminal_size();
+                let mut scroll: Option<(usize, usize)> = None;
+                watch_resize();
                 loop {
                     // swap background/shared buffers if updated
-                    let done = {
+                    let (done, updated) = {
                         let mut shared = shared.lock().unwrap();
-                        if shared.1.len() > 0 {
+                        let updated = !shared.1.is_empty();
+                        if updated {
+                            mem::swap(&mut previous, &mut background);
                             background.clear();
                             mem::swap(&mut shared.1, &mut background);
                         }
-                        shared.0
+                        (shared.0, updated)
                     };
 
-                    // how many lines do we want to show
-                    let lines_ = str::from_utf8(&background).unwrap()
+                    // did our terminal change size? if so clear our canvas
+                    // and start over
+                    if resized() {
+                        size = terminal_size();
+                        if lines > 1 {
+                            // \x1b[nA => move cursor up n lines
+                            print!("\r\x1b[{}A", lines-1);
+                        }
+                        // \x1b[J => clear to end of screen
+                        print!("\r\x1b[J");
+                        lines = 1;
+                    }
+
+                    let frame = str::from_utf8(&background).unwrap()
                         .lines()
-                        .count();
+                        .collect::<Vec<_>>();
+                    let width = frame.iter()
+                        .map(|line| line.chars().count())
+                        .max()
+                        .unwrap_or(0);
 
-                    // optionally limit the number of lines we print
-                    let (skip, lines_) = match limit {
-                        Some(limit) if limit < lines_ => {
-                            (lines_ - limit, limit)
-                        }
-                        _ => {
-                            (0, lines_)
+                    // how many lines/columns can we show? leave one line
+                    // for the cursor
+                    let (cols, rows) = size
+                        .map(|(cols, rows)| (cols, rows.saturating_sub(1)))
+                        .unwrap_or((usize::MAX, usize::MAX));
+                    let lines_ = frame.len()
+                        .min(limit.unwrap_or(usize::MAX))
+                        .min(cmp::max(rows, 1));
+                    let cols = cmp::max(cols, 1);
+
+                    // scroll to the most recently changed region, by
+                    // default we show the end of our frame
+                    if updated {
+                        if let Some(((lo_y, hi_y), (lo_x, hi_x))) = changed(
+                            str::from_utf8(&previous).unwrap(),
+                            &frame,
+                        ) {
+                            scroll = Some((
+                                ((lo_y+hi_y)/2).saturating_sub(lines_/2),
+                                ((lo_x+hi_x)/2).saturating_sub(cols/2),
+                            ));
                         }
-                    };
+                    }
+                    let (skip, skip_x) = scroll
+                        .unwrap_or((usize::MAX, 0));
+                    let skip = skip.min(frame.len() - lines_);
+                    let skip_x = skip_x.min(width.saturating_sub(cols));
 
                     // give ourselves a canvas
-                    //
-                    // NOTE in theory we should limit how many newlines we
-                    // print by the terminal size, but that's more complicate
-                    // than I'm interested in right now
                     while lines_ > lines {
                         println!();
                         lines += 1;
                     }
 
-                    for (i, line) in
-                        str::from_utf8(&background).unwrap()
-                            .lines()
-                            .skip(skip)
-                            .enumerate()
+                    for (i, line) in frame.iter()
+                        .skip(skip)
+                        .take(lines_)
+                        .enumerate()
                     {
+                        // crop to our terminal
+                        let line = line.chars()
+                            .skip(skip_x)
+                            .take(cols)
+                            .collect::<String>();
+
                         // reset cursor, note we move from the bottom to
                         // let the terminal limit our cursor movement
                         print!("\r");
@@ -101,15 +314,40 @@ impl BackgroundTerminal {
         });
 
         Self{
-            shared: shared,
+            shared,
             foreground: vec![],
             handle: Some(handle),
+            recorder: None,
+            gif: None,
         }
     }
 
+    // also record each frame to an asciinema cast file
+    pub fn record(&mut self, path: &Path) -> io::Result<()> {
+        self.recorder = Some(Recorder::new(path)?);
+        Ok(())
+    }
+
+    // also record each frame to an animated gif, see GifRecorder
+    pub fn record_gif(
+        &mut self,
+        path: &Path,
+        delay: f64,
+        skip: usize,
+    ) -> io::Result<()> {
+        self.gif = Some(GifRecorder::new(path, delay, skip)?);
+        Ok(())
+    }
+
     // swap foreground/shared buffers, updating the terminal and preparing
     // for a new write
     pub fn swap(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frame(&self.foreground);
+        }
+        if let Some(gif) = &mut self.gif {
+            gif.frame(&self.foreground);
+        }
         mem::swap(&mut self.shared.lock().unwrap().1, &mut self.foreground);
         self.foreground.clear();
     }
@@ -132,6 +370,12 @@ impl Drop for BackgroundTerminal {
     fn drop(&mut self) {
         self.shared.lock().unwrap().0 = true;
         self.handle.take().unwrap().join().unwrap();
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish().unwrap();
+        }
+        if let Some(gif) = self.gif.take() {
+            gif.finish().unwrap();
+        }
         // sneaky newline
         println!();
     }
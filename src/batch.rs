@@ -0,0 +1,232 @@
+use structopt::StructOpt;
+use rand::{self, RngCore};
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::*;
+
+
+// generate many stations at once, to find good seeds
+//
+// this accepts all of the normal options, with a few differences:
+// - --seed is the first seed in a range of seeds, otherwise seeds are random
+// - --output is a directory to write json/renders/a summary csv into
+// - --small-map/--bubble-map/--tile-map choose which renders to write
+// - acceptance criteria don't re-roll, they only mark stations as accepted
+//   in the summary
+//
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name="batch",
+    about="Generate many stations at once, to find good seeds.",
+    rename_all="kebab"
+)]
+pub struct BatchOpt {
+    #[structopt(flatten)]
+    opt: Opt,
+
+    /// Number of stations to generate.
+    #[structopt(
+        short="n",
+        long,
+        default_value="10",
+        parse(try_from_str=parse_usize)
+    )]
+    count: usize,
+
+    /// Number of threads, defaults to the available parallelism.
+    #[structopt(short="j", long, parse(try_from_str=parse_usize))]
+    threads: Option<usize>,
+}
+
+// write a rendered map to a file
+fn write_map(
+    path: &Path,
+    (width, height, map): (usize, usize, Vec<char>),
+) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    for y in 0..height {
+        writeln!(f, "{}",
+            map[y*width..(y+1)*width].iter().collect::<String>()
+        )?;
+    }
+    Ok(())
+}
+
+// generate one station and write everything out, returning its row in our
+// summary csv
+fn batch_one(opt: &Opt, dir: &Path, seed: u64) -> io::Result<String> {
+    let generation = generate(opt, seed)?;
+    let report = GenerationReport::new(&generation);
+    let name = format!("0x{:016x}", seed);
+
+    if generation.success {
+        let mut f = File::create(dir.join(format!("{}.json", name)))?;
+        // a single deck is written as-is, multiple decks are written
+        // along with their lifts
+        if generation.decks.decks.len() > 1 {
+            serde_json::to_writer(&mut f, &generation.decks)?;
+        } else {
+            serde_json::to_writer(&mut f, &generation.decks.decks[0])?;
+        }
+    }
+
+    for (i, ws) in generation.decks.decks.iter().enumerate() {
+        // only suffix decks if we have more than one
+        let name = if generation.decks.decks.len() > 1 {
+            format!("{}_deck{}", name, i)
+        } else {
+            name.clone()
+        };
+
+        if opt.small_map {
+            write_map(
+                &dir.join(format!("{}_small.txt", name)),
+                ws.render_small_map(opt.small_width, opt.small_height),
+            )?;
+        }
+        if opt.bubble_map {
+            write_map(
+                &dir.join(format!("{}_bubbles.txt", name)),
+                ws.render_bubble_map(),
+            )?;
+        }
+        if opt.tile_map {
+            write_map(
+                &dir.join(format!("{}_tiles.txt", name)),
+                ws.render_tile_map(),
+            )?;
+        }
+    }
+
+    // sum up our decks
+    let decks = &report.decks;
+    let sum = |f: fn(&DeckReport) -> u64| -> u64 {
+        decks.iter().map(f).sum()
+    };
+    Ok(format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{:.6},{:.6},\
+            {:.6},{}",
+        name,
+        report.success,
+        decks.len(),
+        sum(|deck| deck.size as u64),
+        sum(|deck| deck.bubbles as u64),
+        decks.iter().map(|deck| deck.width).max().unwrap_or(0),
+        decks.iter().map(|deck| deck.height).max().unwrap_or(0),
+        decks.iter().map(|deck| deck.depth).max().unwrap_or(0),
+        sum(|deck| deck.hallways as u64),
+        sum(|deck| deck.hallway_length as u64),
+        sum(|deck| deck.loops as u64),
+        report.lifts,
+        sum(|deck| deck.doors as u64),
+        sum(|deck| deck.rooms as u64),
+        sum(|deck| deck.dead_ends as u64),
+        sum(|deck| deck.wfc_attempts),
+        sum(|deck| deck.wfc_cycles),
+        sum(|deck| deck.wfc_propagations),
+        decks.iter()
+            .map(|deck| deck.bubble_time.as_secs_f64())
+            .sum::<f64>(),
+        decks.iter()
+            .map(|deck| deck.wfc_time.as_secs_f64())
+            .sum::<f64>(),
+        report.time.as_secs_f64(),
+        opt.criteria.reject(&report).is_none(),
+    ))
+}
+
+pub fn batch(batch: BatchOpt) -> io::Result<()> {
+    let mut opt = batch.opt;
+    // no animations or interactivity in batch mode
+    opt.anim_small = false;
+    opt.anim_bubbles = false;
+    opt.anim_tiles = false;
+    opt.interactive = false;
+    // we always write json, which is also what tells us to run wfc
+    let dir = opt.output.clone().unwrap_or_else(|| PathBuf::from("batch"));
+    opt.output = Some(dir.clone());
+    let opt = opt;
+    fs::create_dir_all(&dir)?;
+
+    // either sweep a range of seeds, or pick random seeds, we pick these
+    // up front so results don't depend on thread scheduling
+    let seeds = match opt.seed {
+        Some(seed) => (0..batch.count as u64)
+            .map(|i| seed.wrapping_add(i))
+            .collect::<Vec<_>>(),
+        None => {
+            let mut rng = rand::thread_rng();
+            (0..batch.count).map(|_| rng.next_u64()).collect()
+        }
+    };
+
+    let threads = batch.threads
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, cmp::max(seeds.len(), 1));
+
+    // each thread takes the next seed until we run out, we also keep track
+    // of how many rows we've printed so we can print them in seed order
+    let next = AtomicUsize::new(0);
+    let rows = Mutex::new((0, vec![None; seeds.len()]));
+    thread::scope(|scope| -> io::Result<()> {
+        let handles = (0..threads)
+            .map(|_| scope.spawn(|| -> io::Result<()> {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let seed = match seeds.get(i) {
+                        Some(&seed) => seed,
+                        None => return Ok(()),
+                    };
+
+                    let row = batch_one(&opt, &dir, seed)?;
+                    let mut rows = rows.lock().unwrap();
+                    let (printed, rows) = &mut *rows;
+                    rows[i] = Some(row);
+                    while let Some(Some(row)) = rows.get(*printed) {
+                        println!("{}", row);
+                        *printed += 1;
+                    }
+                }
+            }))
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        Ok(())
+    })?;
+
+    // write our summary in seed order
+    let (_, rows) = rows.into_inner().unwrap();
+    let mut f = File::create(dir.join("summary.csv"))?;
+    writeln!(f, "seed,success,decks,size,bubbles,width,height,depth,\
+        hallways,hallway_length,loops,lifts,doors,rooms,dead_ends,\
+        wfc_attempts,wfc_cycles,wfc_propagations,bubble_time,wfc_time,time,\
+        accepted")?;
+    let mut failures = 0;
+    let mut accepted = 0;
+    for row in rows {
+        let row = row.unwrap();
+        if row.split(',').nth(1) != Some("true") {
+            failures += 1;
+        }
+        if row.ends_with(",true") {
+            accepted += 1;
+        }
+        writeln!(f, "{}", row)?;
+    }
+
+    println!("generated {} stations, {} failed", seeds.len(), failures);
+    if opt.criteria.any() {
+        println!("{} stations accepted", accepted);
+    }
+    println!("updated {:?}", dir.join("summary.csv"));
+    Ok(())
+}
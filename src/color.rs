@@ -0,0 +1,135 @@
+use std::env;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::constraints::*;
+
+
+// when should we color our maps?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorWhen {
+    // only if stdout is a terminal, and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorWhen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto"   => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            "never"  => Ok(ColorWhen::Never),
+            _ => Err(format!("unknown color mode {:?}", s)),
+        }
+    }
+}
+
+impl ColorWhen {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Auto => {
+                env::var_os("NO_COLOR").is_none()
+                    && unsafe { libc::isatty(libc::STDOUT_FILENO) } != 0
+            }
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+        }
+    }
+}
+
+// styles are ANSI SGR parameters, an empty style is unstyled
+pub type Style = &'static str;
+
+pub const STYLE_NONE: Style         = "";
+pub const STYLE_OUTER_WALL: Style   = "1;37";
+pub const STYLE_INNER_WALL: Style   = "38;5;244";
+pub const STYLE_DOOR: Style         = "48;5;130";
+pub const STYLE_ROOM_FLOOR: Style   = "48;5;235";
+pub const STYLE_HALL_FLOOR: Style   = "48;5;238";
+pub const STYLE_LIFT: Style         = "1;36;48;5;235";
+pub const STYLE_CONFLICT: Style     = "1;31";
+
+// heat colors for unresolved tiles, from few possibilities to many
+const STYLE_HEAT: [Style; 8] = [
+    "38;5;46",
+    "38;5;118",
+    "38;5;190",
+    "38;5;226",
+    "38;5;220",
+    "38;5;214",
+    "38;5;208",
+    "38;5;196",
+];
+
+// colors for bubble depths, cycling if the tree gets too deep
+const STYLE_DEPTH: [Style; 6] = [
+    "31",
+    "33",
+    "32",
+    "36",
+    "34",
+    "35",
+];
+
+// style for a tile based on what sort of tile it is, we need to know if
+// floors are in a room to tell them apart from hallways
+pub fn tile_style(c: u128, in_room: bool) -> Style {
+    match c {
+        0 => STYLE_CONFLICT,
+        c if c.count_ones() > 1 => {
+            STYLE_HEAT[
+                (c.count_ones() as usize - 2).min(STYLE_HEAT.len()-1)
+            ]
+        }
+        c if c & TILE_SPACEISH != 0 => STYLE_NONE,
+        c if c & TILE_LIFT != 0 => STYLE_LIFT,
+        c if c & TILE_DOORISH != 0 => STYLE_DOOR,
+        c if c & TILE_INWALLISH != 0 => STYLE_INNER_WALL,
+        c if c & TILE_WALLISH != 0 => STYLE_OUTER_WALL,
+        _ if in_room => STYLE_ROOM_FLOOR,
+        _ => STYLE_HALL_FLOOR,
+    }
+}
+
+// style for a bubble based on its depth in the parent tree
+pub fn depth_style(depth: usize) -> Style {
+    STYLE_DEPTH[depth % STYLE_DEPTH.len()]
+}
+
+// print a map, optionally styling each char
+pub fn print_map(
+    width: usize,
+    height: usize,
+    map: &[char],
+    styles: Option<&[Style]>,
+) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for y in 0..height {
+        let mut style = STYLE_NONE;
+        for x in 0..width {
+            // only emit escape codes when our style changes
+            if let Some(styles) = styles {
+                let style_ = styles[x+y*width];
+                if style_ != style {
+                    if !style.is_empty() {
+                        // \x1b[0m => reset
+                        write!(stdout, "\x1b[0m").unwrap();
+                    }
+                    if !style_.is_empty() {
+                        write!(stdout, "\x1b[{}m", style_).unwrap();
+                    }
+                    style = style_;
+                }
+            }
+            write!(stdout, "{}", map[x+y*width]).unwrap();
+        }
+        if style != STYLE_NONE {
+            write!(stdout, "\x1b[0m").unwrap();
+        }
+        writeln!(stdout).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use std::str::FromStr;
 
 
 // these types are how we define constraints
@@ -50,6 +51,7 @@ impl Constraints {
 pub struct Tile {
     pub name: &'static str,
     pub ascii: &'static [u8],
+    pub unicode: &'static str,
     pub constraints: Constraints,
 }
 
@@ -57,12 +59,78 @@ impl Tile {
     const fn new(
         name: &'static str,
         ascii: &'static [u8],
+        unicode: &'static str,
         constraints: Constraints
     ) -> Tile {
         Tile{
-            name: name,
-            ascii: ascii,
-            constraints: constraints
+            name,
+            ascii,
+            unicode,
+            constraints
+        }
+    }
+
+    // how we show this tile, always two chars wide
+    pub fn glyphs(&self, charset: Charset) -> [char; 2] {
+        match charset {
+            Charset::Ascii => [self.ascii[0] as char, self.ascii[1] as char],
+            Charset::Unicode => {
+                let mut chars = self.unicode.chars();
+                [chars.next().unwrap(), chars.next().unwrap()]
+            }
+        }
+    }
+}
+
+// box-drawing characters and which way their lines go, n/e/s/w
+const BOX_ARMS: [(char, [bool; 4]); 15] = [
+    ('─', [false, true,  false, true ]),
+    ('│', [true,  false, true,  false]),
+    ('┌', [false, true,  true,  false]),
+    ('┐', [false, false, true,  true ]),
+    ('└', [true,  true,  false, false]),
+    ('┘', [true,  false, false, true ]),
+    ('├', [true,  true,  true,  false]),
+    ('┤', [true,  false, true,  true ]),
+    ('┬', [false, true,  true,  true ]),
+    ('┴', [true,  true,  false, true ]),
+    ('┼', [true,  true,  true,  true ]),
+    ('╭', [false, true,  true,  false]),
+    ('╮', [false, false, true,  true ]),
+    ('╰', [true,  true,  false, false]),
+    ('╯', [true,  false, false, true ]),
+];
+
+// which way do a box-drawing character's lines go?
+pub fn box_arms(c: char) -> Option<[bool; 4]> {
+    BOX_ARMS.iter()
+        .find(|(c_, _)| *c_ == c)
+        .map(|(_, arms)| *arms)
+}
+
+// find the box-drawing character with these lines
+pub fn box_char(arms: [bool; 4]) -> Option<char> {
+    BOX_ARMS.iter()
+        .find(|(_, arms_)| *arms_ == arms)
+        .map(|(c, _)| *c)
+}
+
+// which characters should we render tiles with?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Ascii,
+    // box-drawing characters
+    Unicode,
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii"   => Ok(Charset::Ascii),
+            "unicode" => Ok(Charset::Unicode),
+            _ => Err(format!("unknown charset {:?}", s)),
         }
     }
 }
@@ -171,6 +239,8 @@ pub const TILE_UR_N_TEE_A       : u128 = 1 << 79;
 pub const TILE_UR_N_TEE_B       : u128 = 1 << 80;
 pub const TILE_UR_N_TEE_B2      : u128 = 1 << 81;
 
+pub const TILE_LIFT             : u128 = 1 << 82;
+
 // some common constraints
 pub const TILE_SPACEISH: u128
     = TILE_SPACE
@@ -180,7 +250,8 @@ pub const TILE_SPACEISH: u128
     | TILE_DEGENERATE_W;
 
 pub const TILE_FLOORISH: u128
-    = TILE_FLOOR;
+    = TILE_FLOOR
+    | TILE_LIFT;
 
 pub const TILE_WALLISH: u128
     = TILE_N_WALL
@@ -204,34 +275,48 @@ pub const TILE_WALLISH: u128
     | TILE_SW_WALL4
     | TILE_NW_WALL4;
 
+// note inside walls are all contiguous, so we can cheat a bit here
+pub const TILE_INWALLISH: u128
+    = (TILE_UR_N_TEE_B2 << 1) - TILE_LR_N_INWALL_A;
+
+pub const TILE_DOORISH: u128
+    = TILE_LR_N_DOOR
+    | TILE_LR_E_DOOR
+    | TILE_LL_E_DOOR
+    | TILE_LL_S_DOOR
+    | TILE_UL_S_DOOR
+    | TILE_UL_W_DOOR
+    | TILE_UR_W_DOOR
+    | TILE_UR_N_DOOR;
+
 
 // all tiles in our system
 //
 // I sure do love changings this array size everytime I tweak anything here
 //
-pub const TILES: [Tile; 82] = [
+pub const TILES: [Tile; 83] = [
     // space
-    Tile::new("space", b"  ", Constraints::ALL),
+    Tile::new("space", b"  ", "  ", Constraints::ALL),
 
-    Tile::new("degenerate-n", b"  ", Constraints{
+    Tile::new("degenerate-n", b"  ", "  ", Constraints{
         n: TILE_SPACE,
         e: TILE_SPACE,
         s: !TILE_SPACE,
         w: TILE_SPACE,
     }),
-    Tile::new("degenerate-e", b"  ", Constraints{
+    Tile::new("degenerate-e", b"  ", "  ", Constraints{
         n: TILE_SPACE,
         e: TILE_SPACE,
         s: TILE_SPACE,
         w: !TILE_SPACE,
     }),
-    Tile::new("degenerate-s", b"  ", Constraints{
+    Tile::new("degenerate-s", b"  ", "  ", Constraints{
         n: !TILE_SPACE,
         e: TILE_SPACE,
         s: TILE_SPACE,
         w: TILE_SPACE,
     }),
-    Tile::new("degenerate-w", b"  ", Constraints{
+    Tile::new("degenerate-w", b"  ", "  ", Constraints{
         n: TILE_SPACE,
         e: !TILE_SPACE,
         s: TILE_SPACE,
@@ -239,7 +324,7 @@ pub const TILES: [Tile; 82] = [
     }),
 
     // inside things
-    Tile::new("floor", b"  ", Constraints{
+    Tile::new("floor", b"  ", "  ", Constraints{
         n: !TILE_SPACEISH,
         e: !TILE_SPACEISH,
         s: !TILE_SPACEISH,
@@ -247,125 +332,125 @@ pub const TILES: [Tile; 82] = [
     }),
 
     // outside walls
-    Tile::new("n-wall", b"--", Constraints{
+    Tile::new("n-wall", b"--", "──", Constraints{
         n: TILE_SPACEISH,
         e: TILE_WALLISH,
         s: !TILE_SPACEISH,
         w: TILE_WALLISH,
     }),
-    Tile::new("e-wall", b"| ", Constraints{
+    Tile::new("e-wall", b"| ", "│ ", Constraints{
         n: !TILE_SPACEISH,
         e: TILE_SPACEISH,
         s: !TILE_SPACEISH,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("s-wall", b"--", Constraints{
+    Tile::new("s-wall", b"--", "──", Constraints{
         n: !TILE_SPACEISH,
         e: TILE_WALLISH,
         s: TILE_SPACEISH,
         w: TILE_WALLISH,
     }),
-    Tile::new("w-wall", b" |", Constraints{
+    Tile::new("w-wall", b" |", " │", Constraints{
         n: TILE_WALLISH,
         e: !TILE_SPACEISH,
         s: TILE_WALLISH,
         w: TILE_SPACEISH,
     }),
 
-    Tile::new("ne-wall", b". ", Constraints{
+    Tile::new("ne-wall", b". ", "╮ ", Constraints{
         n: TILE_SPACEISH,
         e: TILE_SPACEISH,
         s: TILE_WALLISH,
         w: TILE_WALLISH,
     }),
-    Tile::new("se-wall", b"' ", Constraints{
+    Tile::new("se-wall", b"' ", "╯ ", Constraints{
         n: TILE_WALLISH,
         e: TILE_SPACEISH,
         s: TILE_SPACEISH,
         w: TILE_WALLISH,
     }),
-    Tile::new("sw-wall", b" '", Constraints{
+    Tile::new("sw-wall", b" '", " ╰", Constraints{
         n: TILE_WALLISH,
         e: TILE_WALLISH,
         s: TILE_SPACEISH,
         w: TILE_SPACEISH,
     }),
-    Tile::new("nw-wall", b" .", Constraints{
+    Tile::new("nw-wall", b" .", " ╭", Constraints{
         n: TILE_SPACEISH,
         e: TILE_WALLISH,
         s: TILE_WALLISH,
         w: TILE_SPACEISH,
     }),
 
-    Tile::new("ne-wall2", b" '", Constraints{
+    Tile::new("ne-wall2", b" '", "╰─", Constraints{
         n: TILE_NE_WALL,
         e: TILE_NE_WALL,
         s: !TILE_SPACEISH,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("se-wall2", b" .", Constraints{
+    Tile::new("se-wall2", b" .", "╭─", Constraints{
         n: !TILE_SPACEISH,
         e: TILE_SE_WALL,
         s: TILE_SE_WALL,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("sw-wall2", b". ", Constraints{
+    Tile::new("sw-wall2", b". ", "─╮", Constraints{
         n: !TILE_SPACEISH,
         e: !TILE_SPACEISH,
         s: TILE_SW_WALL,
         w: TILE_SW_WALL,
     }),
-    Tile::new("nw-wall2", b"' ", Constraints{
+    Tile::new("nw-wall2", b"' ", "─╯", Constraints{
         n: TILE_NW_WALL,
         e: !TILE_SPACEISH,
         s: !TILE_SPACEISH,
         w: TILE_NW_WALL,
     }),
 
-    Tile::new("ne-wall3", b"+-", Constraints{
+    Tile::new("ne-wall3", b"+-", "└─", Constraints{
         n: TILE_E_WALL,
         e: TILE_NE_WALL|TILE_N_WALL,
         s: !TILE_SPACEISH,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("se-wall3", b"+-", Constraints{
+    Tile::new("se-wall3", b"+-", "┌─", Constraints{
         n: !TILE_SPACEISH,
         e: TILE_S_WALL,
         s: TILE_SE_WALL|TILE_E_WALL,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("sw-wall3", b"-+", Constraints{
+    Tile::new("sw-wall3", b"-+", "─┐", Constraints{
         n: !TILE_SPACEISH,
         e: !TILE_SPACEISH,
         s: TILE_W_WALL,
         w: TILE_SW_WALL|TILE_S_WALL,
     }),
-    Tile::new("nw-wall3", b"-+", Constraints{
+    Tile::new("nw-wall3", b"-+", "─┘", Constraints{
         n: TILE_NW_WALL|TILE_W_WALL,
         e: !TILE_SPACEISH,
         s: !TILE_SPACEISH,
         w: TILE_N_WALL,
     }),
 
-    Tile::new("ne-wall4", b"+-", Constraints{
+    Tile::new("ne-wall4", b"+-", "└─", Constraints{
         n: TILE_NE_WALL|TILE_E_WALL,
         e: TILE_N_WALL,
         s: !TILE_SPACEISH,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("se-wall4", b"+-", Constraints{
+    Tile::new("se-wall4", b"+-", "┌─", Constraints{
         n: !TILE_SPACEISH,
         e: TILE_SE_WALL|TILE_S_WALL,
         s: TILE_E_WALL,
         w: !TILE_SPACEISH,
     }),
-    Tile::new("sw-wall4", b"-+", Constraints{
+    Tile::new("sw-wall4", b"-+", "─┐", Constraints{
         n: !TILE_SPACEISH,
         e: !TILE_SPACEISH,
         s: TILE_SW_WALL|TILE_W_WALL,
         w: TILE_S_WALL,
     }),
-    Tile::new("nw-wall4", b"-+", Constraints{
+    Tile::new("nw-wall4", b"-+", "─┘", Constraints{
         n: TILE_W_WALL,
         e: !TILE_SPACEISH,
         s: !TILE_SPACEISH,
@@ -379,86 +464,86 @@ pub const TILES: [Tile; 82] = [
     // note this forms a sort of fsm that preserves a "one door for each room"
     // rull via coloring
     //
-    Tile::new("lr-n-inwall-a", b"| ", Constraints{
+    Tile::new("lr-n-inwall-a", b"| ", "│ ", Constraints{
         n: TILE_LR_N_INWALL_A | TILE_LR_N_DOOR | TILE_LR_N_JOINT_A | TILE_LR_N_TEE_A | TILE_LR_E_TEE_B,
         e: TILE_FLOORISH | TILE_WALLISH,
         s: TILE_WALLISH | TILE_LR_N_INWALL_A | TILE_LR_N_TEE_A | TILE_LR_N_TEE_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-door", b"  ", Constraints{
+    Tile::new("lr-n-door", b"  ", "  ", Constraints{
         n: TILE_LR_N_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_LR_N_INWALL_A,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-inwall-b", b"| ", Constraints{
+    Tile::new("lr-n-inwall-b", b"| ", "│ ", Constraints{
         n: TILE_WALLISH | TILE_LR_N_INWALL_B | TILE_LR_N_JOINT_B | TILE_LR_N_TEE_B | TILE_LR_N_TEE_B2 | TILE_LR_E_TEE_A | TILE_LR_E_TEE_B2,
         e: TILE_FLOORISH | TILE_WALLISH,
         s: TILE_LR_N_INWALL_B | TILE_LR_N_DOOR | TILE_LR_N_TEE_B2,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-joint-a", b".-", Constraints{
+    Tile::new("lr-n-joint-a", b".-", "┌─", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_A,
         s: TILE_LR_N_INWALL_A,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-joint-b", b".-", Constraints{
+    Tile::new("lr-n-joint-b", b".-", "┌─", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_B,
         s: TILE_LR_N_INWALL_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-tee-a", b"+-", Constraints{
+    Tile::new("lr-n-tee-a", b"+-", "├─", Constraints{
         n: TILE_LR_N_INWALL_A,
         e: TILE_LR_E_INWALL_A,
         s: TILE_LR_N_INWALL_A,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-tee-b", b"+-", Constraints{
+    Tile::new("lr-n-tee-b", b"+-", "├─", Constraints{
         n: TILE_LR_N_INWALL_A,
         e: TILE_LR_E_INWALL_B,
         s: TILE_LR_N_INWALL_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("lr-n-tee-b2", b"+-", Constraints{
+    Tile::new("lr-n-tee-b2", b"+-", "├─", Constraints{
         n: TILE_LR_N_INWALL_B,
         e: TILE_LR_E_INWALL_A,
         s: TILE_LR_N_INWALL_B,
         w: TILE_FLOORISH,
     }),
 
-    Tile::new("lr-e-inwall-a", b"--", Constraints{
+    Tile::new("lr-e-inwall-a", b"--", "──", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_A | TILE_LR_E_DOOR | TILE_LR_E_TEE_A,
         s: TILE_FLOORISH | TILE_WALLISH,
         w: TILE_WALLISH | TILE_LR_E_INWALL_A | TILE_LR_N_JOINT_A | TILE_LR_N_TEE_A | TILE_LR_N_TEE_B2 | TILE_LR_E_TEE_A | TILE_LR_E_TEE_B,
     }),
-    Tile::new("lr-e-door", b"  ", Constraints{
+    Tile::new("lr-e-door", b"  ", "  ", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_LR_E_INWALL_A,
     }),
-    Tile::new("lr-e-inwall-b", b"--", Constraints{
+    Tile::new("lr-e-inwall-b", b"--", "──", Constraints{
         n: TILE_FLOORISH,
         e: TILE_WALLISH | TILE_LR_E_INWALL_B | TILE_LR_N_JOINT_B | TILE_LR_E_TEE_B | TILE_LR_E_TEE_B2,
         s: TILE_FLOORISH | TILE_WALLISH,
         w: TILE_LR_E_INWALL_B | TILE_LR_E_DOOR | TILE_LR_N_JOINT_B | TILE_LR_E_TEE_B2,
     }),
-    Tile::new("lr-e-tee-a", b"+-", Constraints{
+    Tile::new("lr-e-tee-a", b"+-", "┬─", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_A,
         s: TILE_LR_N_INWALL_B,
         w: TILE_LR_E_INWALL_A,
     }),
-    Tile::new("lr-e-tee-b", b"+-", Constraints{
+    Tile::new("lr-e-tee-b", b"+-", "┬─", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_A,
         s: TILE_LR_N_INWALL_A,
         w: TILE_LR_E_INWALL_B,
     }),
-    Tile::new("lr-e-tee-b2", b"+-", Constraints{
+    Tile::new("lr-e-tee-b2", b"+-", "┬─", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LR_E_INWALL_B,
         s: TILE_LR_N_INWALL_B,
@@ -466,86 +551,86 @@ pub const TILES: [Tile; 82] = [
     }),
 
     //
-    Tile::new("ll-e-inwall-a", b"--", Constraints{
+    Tile::new("ll-e-inwall-a", b"--", "──", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LL_E_INWALL_A | TILE_LL_E_DOOR | TILE_LL_E_JOINT_A | TILE_LL_E_TEE_A | TILE_LL_S_TEE_B,
         s: TILE_FLOORISH | TILE_WALLISH,
         w: TILE_WALLISH | TILE_LL_E_INWALL_A | TILE_LL_E_TEE_A | TILE_LL_E_TEE_B,
     }),
-    Tile::new("ll-e-door", b"  ", Constraints{
+    Tile::new("ll-e-door", b"  ", "  ", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LL_E_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_LL_E_INWALL_A,
     }),
-    Tile::new("ll-e-inwall-b", b"--", Constraints{
+    Tile::new("ll-e-inwall-b", b"--", "──", Constraints{
         n: TILE_FLOORISH,
         e: TILE_WALLISH | TILE_LL_E_INWALL_B | TILE_LL_E_JOINT_B | TILE_LL_E_TEE_B | TILE_LL_E_TEE_B2 | TILE_LL_S_TEE_A | TILE_LL_S_TEE_B2,
         s: TILE_FLOORISH | TILE_WALLISH,
         w: TILE_LL_E_INWALL_B | TILE_LL_E_DOOR | TILE_LL_E_TEE_B2,
     }),
-    Tile::new("ll-e-joint-a", b"-.", Constraints{
+    Tile::new("ll-e-joint-a", b"-.", "─┐", Constraints{
         n: TILE_FLOORISH,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_A,
         w: TILE_LL_E_INWALL_A,
     }),
-    Tile::new("ll-e-joint-b", b"-.", Constraints{
+    Tile::new("ll-e-joint-b", b"-.", "─┐", Constraints{
         n: TILE_FLOORISH,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_B,
         w: TILE_LL_E_INWALL_B,
     }),
-    Tile::new("ll-e-tee-a", b"-+", Constraints{
+    Tile::new("ll-e-tee-a", b"-+", "─┬", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LL_E_INWALL_A,
         s: TILE_LL_S_INWALL_A,
         w: TILE_LL_E_INWALL_A,
     }),
-    Tile::new("ll-e-tee-b", b"-+", Constraints{
+    Tile::new("ll-e-tee-b", b"-+", "─┬", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LL_E_INWALL_A,
         s: TILE_LL_S_INWALL_B,
         w: TILE_LL_E_INWALL_B,
     }),
-    Tile::new("ll-e-tee-b2", b"-+", Constraints{
+    Tile::new("ll-e-tee-b2", b"-+", "─┬", Constraints{
         n: TILE_FLOORISH,
         e: TILE_LL_E_INWALL_B,
         s: TILE_LL_S_INWALL_A,
         w: TILE_LL_E_INWALL_B,
     }),
 
-    Tile::new("ll-s-inwall-a", b" |", Constraints{
+    Tile::new("ll-s-inwall-a", b" |", " │", Constraints{
         n: TILE_WALLISH | TILE_LL_S_INWALL_A | TILE_LL_E_JOINT_A | TILE_LL_E_TEE_A | TILE_LL_E_TEE_B2 | TILE_LL_S_TEE_A | TILE_LL_S_TEE_B,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_A | TILE_LL_S_DOOR | TILE_LL_S_TEE_A,
         w: TILE_FLOORISH | TILE_WALLISH,
     }),
-    Tile::new("ll-s-door", b"  ", Constraints{
+    Tile::new("ll-s-door", b"  ", "  ", Constraints{
         n: TILE_LL_S_INWALL_A,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ll-s-inwall-b", b" |", Constraints{
+    Tile::new("ll-s-inwall-b", b" |", " │", Constraints{
         n: TILE_LL_S_INWALL_B | TILE_LL_S_DOOR | TILE_LL_E_JOINT_B | TILE_LL_S_TEE_B2,
         e: TILE_FLOORISH,
         s: TILE_WALLISH | TILE_LL_S_INWALL_B | TILE_LL_E_JOINT_B | TILE_LL_S_TEE_B | TILE_LL_S_TEE_B2,
         w: TILE_FLOORISH | TILE_WALLISH,
     }),
-    Tile::new("ll-s-tee-a", b"-+", Constraints{
+    Tile::new("ll-s-tee-a", b"-+", "─┤", Constraints{
         n: TILE_LL_S_INWALL_A,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_A,
         w: TILE_LL_E_INWALL_B,
     }),
-    Tile::new("ll-s-tee-b", b"-+", Constraints{
+    Tile::new("ll-s-tee-b", b"-+", "─┤", Constraints{
         n: TILE_LL_S_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_A,
         w: TILE_LL_E_INWALL_A,
     }),
-    Tile::new("ll-s-tee-b2", b"-+", Constraints{
+    Tile::new("ll-s-tee-b2", b"-+", "─┤", Constraints{
         n: TILE_LL_S_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_LL_S_INWALL_B,
@@ -553,86 +638,86 @@ pub const TILES: [Tile; 82] = [
     }),
 
     //
-    Tile::new("ul-s-inwall-a", b" |", Constraints{
+    Tile::new("ul-s-inwall-a", b" |", " │", Constraints{
         n: TILE_WALLISH | TILE_UL_S_INWALL_A | TILE_UL_S_TEE_A | TILE_UL_S_TEE_B,
         e: TILE_FLOORISH,
         s: TILE_UL_S_INWALL_A | TILE_UL_S_DOOR | TILE_UL_S_JOINT_A | TILE_UL_S_TEE_A | TILE_UL_W_TEE_B,
         w: TILE_FLOORISH | TILE_WALLISH,
     }),
-    Tile::new("ul-s-door", b"  ", Constraints{
+    Tile::new("ul-s-door", b"  ", "  ", Constraints{
         n: TILE_UL_S_INWALL_A,
         e: TILE_FLOORISH,
         s: TILE_UL_S_INWALL_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ul-s-inwall-b", b" |", Constraints{
+    Tile::new("ul-s-inwall-b", b" |", " │", Constraints{
         n: TILE_UL_S_INWALL_B | TILE_UL_S_DOOR | TILE_UL_S_TEE_B2,
         e: TILE_FLOORISH,
         s: TILE_WALLISH | TILE_UL_S_INWALL_B | TILE_UL_S_JOINT_B | TILE_UL_S_TEE_B | TILE_UL_S_TEE_B2 | TILE_UL_W_TEE_A | TILE_UL_W_TEE_B2,
         w: TILE_FLOORISH | TILE_WALLISH,
     }),
-    Tile::new("ul-s-joint-a", b"-'", Constraints{
+    Tile::new("ul-s-joint-a", b"-'", "─┘", Constraints{
         n: TILE_UL_S_INWALL_A,
         e: TILE_FLOORISH,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_A,
     }),
-    Tile::new("ul-s-joint-b", b"-'", Constraints{
+    Tile::new("ul-s-joint-b", b"-'", "─┘", Constraints{
         n: TILE_UL_S_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_B,
     }),
-    Tile::new("ul-s-tee-a", b"-+", Constraints{
+    Tile::new("ul-s-tee-a", b"-+", "─┤", Constraints{
         n: TILE_UL_S_INWALL_A,
         e: TILE_FLOORISH,
         s: TILE_UL_S_INWALL_A,
         w: TILE_UL_W_INWALL_A,
     }),
-    Tile::new("ul-s-tee-b", b"-+", Constraints{
+    Tile::new("ul-s-tee-b", b"-+", "─┤", Constraints{
         n: TILE_UL_S_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_UL_S_INWALL_A,
         w: TILE_UL_W_INWALL_B,
     }),
-    Tile::new("ul-s-tee-b2", b"-+", Constraints{
+    Tile::new("ul-s-tee-b2", b"-+", "─┤", Constraints{
         n: TILE_UL_S_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_UL_S_INWALL_B,
         w: TILE_UL_W_INWALL_A,
     }),
 
-    Tile::new("ul-w-inwall-a", b"--", Constraints{
+    Tile::new("ul-w-inwall-a", b"--", "──", Constraints{
         n: TILE_FLOORISH | TILE_WALLISH,
         e: TILE_WALLISH | TILE_UL_W_INWALL_A | TILE_UL_S_JOINT_A | TILE_UL_S_TEE_A | TILE_UL_S_TEE_B2 | TILE_UL_W_TEE_A | TILE_UL_W_TEE_B,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_A | TILE_UL_W_DOOR | TILE_UL_W_TEE_A,
     }),
-    Tile::new("ul-w-door", b"  ", Constraints{
+    Tile::new("ul-w-door", b"  ", "  ", Constraints{
         n: TILE_FLOORISH,
         e: TILE_UL_W_INWALL_A,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_B,
     }),
-    Tile::new("ul-w-inwall-b", b"--", Constraints{
+    Tile::new("ul-w-inwall-b", b"--", "──", Constraints{
         n: TILE_FLOORISH | TILE_WALLISH,
         e: TILE_UL_W_INWALL_B | TILE_UL_W_DOOR | TILE_UL_S_JOINT_B | TILE_UL_W_TEE_B2,
         s: TILE_FLOORISH,
         w: TILE_WALLISH | TILE_UL_W_INWALL_B | TILE_UL_S_JOINT_B | TILE_UL_W_TEE_B | TILE_UL_W_TEE_B2,
     }),
-    Tile::new("ul-w-tee-a", b"-+", Constraints{
+    Tile::new("ul-w-tee-a", b"-+", "─┴", Constraints{
         n: TILE_UL_S_INWALL_B,
         e: TILE_UL_W_INWALL_A,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_A,
     }),
-    Tile::new("ul-w-tee-b", b"-+", Constraints{
+    Tile::new("ul-w-tee-b", b"-+", "─┴", Constraints{
         n: TILE_UL_S_INWALL_A,
         e: TILE_UL_W_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_UL_W_INWALL_A,
     }),
-    Tile::new("ul-w-tee-b2", b"-+", Constraints{
+    Tile::new("ul-w-tee-b2", b"-+", "─┴", Constraints{
         n: TILE_UL_S_INWALL_B,
         e: TILE_UL_W_INWALL_B,
         s: TILE_FLOORISH,
@@ -640,92 +725,104 @@ pub const TILES: [Tile; 82] = [
     }),
 
     //
-    Tile::new("ur-w-inwall-a", b"--", Constraints{
+    Tile::new("ur-w-inwall-a", b"--", "──", Constraints{
         n: TILE_FLOORISH | TILE_WALLISH,
         e: TILE_WALLISH | TILE_UR_W_INWALL_A | TILE_UR_W_TEE_A | TILE_UR_W_TEE_B,
         s: TILE_FLOORISH,
         w: TILE_UR_W_INWALL_A | TILE_UR_W_DOOR | TILE_UR_W_JOINT_A | TILE_UR_W_TEE_A | TILE_UR_N_TEE_B,
     }),
-    Tile::new("ur-w-door", b"  ", Constraints{
+    Tile::new("ur-w-door", b"  ", "  ", Constraints{
         n: TILE_FLOORISH,
         e: TILE_UR_W_INWALL_A,
         s: TILE_FLOORISH,
         w: TILE_UR_W_INWALL_B,
     }),
-    Tile::new("ur-w-inwall-b", b"--", Constraints{
+    Tile::new("ur-w-inwall-b", b"--", "──", Constraints{
         n: TILE_FLOORISH | TILE_WALLISH,
         e: TILE_UR_W_INWALL_B | TILE_UR_W_DOOR | TILE_UR_W_TEE_B2,
         s: TILE_FLOORISH,
         w: TILE_WALLISH | TILE_UR_W_INWALL_B | TILE_UR_W_JOINT_B | TILE_UR_W_TEE_B | TILE_UR_W_TEE_B2 | TILE_UR_N_TEE_A | TILE_UR_N_TEE_B2,
     }),
-    Tile::new("ur-w-joint-a", b"'-", Constraints{
+    Tile::new("ur-w-joint-a", b"'-", "└─", Constraints{
         n: TILE_UR_N_INWALL_A,
         e: TILE_UR_W_INWALL_A,
         s: TILE_FLOORISH,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-w-joint-b", b"'-", Constraints{
+    Tile::new("ur-w-joint-b", b"'-", "└─", Constraints{
         n: TILE_UR_N_INWALL_B,
         e: TILE_UR_W_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-w-tee-a", b"+-", Constraints{
+    Tile::new("ur-w-tee-a", b"+-", "┴─", Constraints{
         n: TILE_UR_N_INWALL_A,
         e: TILE_UR_W_INWALL_A,
         s: TILE_FLOORISH,
         w: TILE_UR_W_INWALL_A,
     }),
-    Tile::new("ur-w-tee-b", b"+-", Constraints{
+    Tile::new("ur-w-tee-b", b"+-", "┴─", Constraints{
         n: TILE_UR_N_INWALL_B,
         e: TILE_UR_W_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_UR_W_INWALL_A,
     }),
-    Tile::new("ur-w-tee-b2", b"+-", Constraints{
+    Tile::new("ur-w-tee-b2", b"+-", "┴─", Constraints{
         n: TILE_UR_N_INWALL_A,
         e: TILE_UR_W_INWALL_B,
         s: TILE_FLOORISH,
         w: TILE_UR_W_INWALL_B,
     }),
 
-    Tile::new("ur-n-inwall-a", b"| ", Constraints{
+    Tile::new("ur-n-inwall-a", b"| ", "│ ", Constraints{
         n: TILE_UR_N_INWALL_A | TILE_UR_N_DOOR | TILE_UR_N_TEE_A,
         e: TILE_FLOORISH | TILE_WALLISH,
         s: TILE_WALLISH | TILE_UR_N_INWALL_A | TILE_UR_W_JOINT_A | TILE_UR_W_TEE_A | TILE_UR_W_TEE_B2 | TILE_UR_N_TEE_A | TILE_UR_N_TEE_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-n-door", b"  ", Constraints{
+    Tile::new("ur-n-door", b"  ", "  ", Constraints{
         n: TILE_UR_N_INWALL_B,
         e: TILE_FLOORISH,
         s: TILE_UR_N_INWALL_A,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-n-inwall-b", b"| ", Constraints{
+    Tile::new("ur-n-inwall-b", b"| ", "│ ", Constraints{
         n: TILE_WALLISH | TILE_UR_N_INWALL_B | TILE_UR_W_JOINT_B | TILE_UR_N_TEE_B | TILE_UR_N_TEE_B2,
         e: TILE_FLOORISH | TILE_WALLISH,
         s: TILE_UR_N_INWALL_B | TILE_UR_N_DOOR | TILE_UR_W_JOINT_B | TILE_UR_N_TEE_B2,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-n-tee-a", b"+-", Constraints{
+    Tile::new("ur-n-tee-a", b"+-", "├─", Constraints{
         n: TILE_UR_N_INWALL_A,
         e: TILE_UR_W_INWALL_B,
         s: TILE_UR_N_INWALL_A,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-n-tee-b", b"+-", Constraints{
+    Tile::new("ur-n-tee-b", b"+-", "├─", Constraints{
         n: TILE_UR_N_INWALL_A,
         e: TILE_UR_W_INWALL_A,
         s: TILE_UR_N_INWALL_B,
         w: TILE_FLOORISH,
     }),
-    Tile::new("ur-n-tee-b2", b"+-", Constraints{
+    Tile::new("ur-n-tee-b2", b"+-", "├─", Constraints{
         n: TILE_UR_N_INWALL_B,
         e: TILE_UR_W_INWALL_B,
         s: TILE_UR_N_INWALL_B,
         w: TILE_FLOORISH,
     }),
+
+    // lifts between decks, these act like floors, but are only ever
+    // placed explicitly
+    Tile::new("lift", b"[]", "[]", Constraints{
+        n: !TILE_SPACEISH,
+        e: !TILE_SPACEISH,
+        s: !TILE_SPACEISH,
+        w: !TILE_SPACEISH,
+    }),
 ];
 
 // a convenience mask for all tiles
 pub const TILE_ALL: u128 = (1u128 << TILES.len()) - 1;
+
+// tiles that are only ever placed explicitly
+pub const TILE_PINNED: u128 = TILE_LIFT;
@@ -0,0 +1,160 @@
+use structopt::StructOpt;
+
+use crate::*;
+
+
+// acceptance criteria for generated stations, if any of these are provided
+// we keep re-rolling seeds until a station passes
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(rename_all="kebab")]
+pub struct Criteria {
+    /// Reject stations with fewer rooms, requires wfc.
+    #[structopt(long, parse(try_from_str=parse_usize))]
+    min_rooms: Option<usize>,
+
+    /// Reject stations where more than this ratio of bubbles are dead ends.
+    #[structopt(long)]
+    max_dead_ends: Option<f64>,
+
+    /// Reject stations with a more stretched bounding box than this aspect
+    /// ratio.
+    #[structopt(long)]
+    max_aspect: Option<f64>,
+
+    /// Reject stations with fewer loops.
+    #[structopt(long, parse(try_from_str=parse_usize))]
+    min_loops: Option<usize>,
+
+    /// Reject stations that spend longer than this in wfc, in seconds.
+    #[structopt(long)]
+    max_wfc_time: Option<f64>,
+
+    /// Number of times to re-roll the seed before giving up.
+    #[structopt(long, default_value="100", parse(try_from_str=parse_usize))]
+    pub max_rerolls: usize,
+}
+
+impl Criteria {
+    // do we have any criteria at all?
+    pub fn any(&self) -> bool {
+        self.min_rooms.is_some()
+            || self.max_dead_ends.is_some()
+            || self.max_aspect.is_some()
+            || self.min_loops.is_some()
+            || self.max_wfc_time.is_some()
+    }
+
+    // do we need to run wfc to check our criteria?
+    pub fn wfc(&self) -> bool {
+        self.min_rooms.is_some()
+    }
+
+    // check a generation against our criteria, returning why it was
+    // rejected if it doesn't pass
+    pub fn reject(&self, report: &GenerationReport) -> Option<String> {
+        if !report.success {
+            return Some("failed to resolve constraints".into());
+        }
+
+        let decks = &report.decks;
+        if let Some(min_rooms) = self.min_rooms {
+            let rooms = decks.iter().map(|deck| deck.rooms).sum::<usize>();
+            if rooms < min_rooms {
+                return Some(format!("{} rooms < {}", rooms, min_rooms));
+            }
+        }
+
+        if let Some(max_dead_ends) = self.max_dead_ends {
+            let dead_ends = decks.iter()
+                .map(|deck| deck.dead_ends)
+                .sum::<usize>();
+            let bubbles = decks.iter()
+                .map(|deck| deck.bubbles)
+                .sum::<usize>();
+            let ratio = dead_ends as f64 / cmp::max(bubbles, 1) as f64;
+            if ratio > max_dead_ends {
+                return Some(format!(
+                    "{:.2} dead ends > {:.2}",
+                    ratio, max_dead_ends
+                ));
+            }
+        }
+
+        if let Some(max_aspect) = self.max_aspect {
+            let aspect = decks.iter()
+                .map(|deck| {
+                    let long = cmp::max(deck.width, deck.height);
+                    let short = cmp::min(deck.width, deck.height);
+                    let short = cmp::max(short, 1);
+                    long as f64 / short as f64
+                })
+                .fold(0.0, f64::max);
+            if aspect > max_aspect {
+                return Some(format!(
+                    "{:.2} aspect > {:.2}",
+                    aspect, max_aspect
+                ));
+            }
+        }
+
+        if let Some(min_loops) = self.min_loops {
+            let loops = decks.iter().map(|deck| deck.loops).sum::<usize>();
+            if loops < min_loops {
+                return Some(format!("{} loops < {}", loops, min_loops));
+            }
+        }
+
+        if let Some(max_wfc_time) = self.max_wfc_time {
+            let wfc_time = decks.iter()
+                .map(|deck| deck.wfc_time.as_secs_f64())
+                .sum::<f64>();
+            if wfc_time > max_wfc_time {
+                return Some(format!(
+                    "{:.3}s wfc > {:.3}s",
+                    wfc_time, max_wfc_time
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+// generate stations until one passes our criteria, re-rolling the seed
+// with a prng so the sequence of seeds is reproducible
+//
+// returns the accepted generation, or the last one if we gave up, along
+// with any rejected seeds and why
+pub fn generate_accepted(
+    opt: &Opt,
+    seed: u64,
+) -> io::Result<(Generation, Vec<(u64, String)>)> {
+    let mut prng = Xorshift64(seed);
+    let mut seed = seed;
+    let mut rejected = vec![];
+    loop {
+        let generation = generate(opt, seed)?;
+        if !opt.criteria.any() {
+            return Ok((generation, rejected));
+        }
+
+        let reason = match opt.criteria.reject(
+            &GenerationReport::new(&generation)
+        ) {
+            Some(reason) => reason,
+            None => return Ok((generation, rejected)),
+        };
+
+        rejected.push((seed, reason));
+        if rejected.len() > opt.criteria.max_rerolls {
+            return Ok((generation, rejected));
+        }
+
+        // xorshift gets stuck at zero
+        seed = prng.next_u64();
+        if seed == 0 {
+            prng = Xorshift64(1);
+            seed = prng.next_u64();
+        }
+    }
+}
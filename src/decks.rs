@@ -0,0 +1,238 @@
+use serde::{self, Serialize};
+use serde::ser::SerializeSeq;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::collections::HashMap;
+use std::mem;
+
+use rand::{self, RngCore};
+
+use crate::*;
+
+
+// a lift connects a bubble on one deck to a bubble on the deck above
+#[derive(Debug, Clone)]
+pub struct Lift {
+    // lower deck, the upper deck is always deck+1
+    pub deck: usize,
+    pub a: Rc<RefCell<Bubble>>,
+    pub b: Rc<RefCell<Bubble>>,
+    // offset from each bubble's center in tiles
+    pub a_off: (isize, isize),
+    pub b_off: (isize, isize),
+}
+
+// a station made of multiple decks stacked on top of each other
+//
+// each deck is its own WaveStation, aligned so their root bubbles at 0,0
+// sit on top of each other
+//
+#[derive(Debug, Clone)]
+pub struct Decks {
+    pub decks: Vec<WaveStation>,
+    pub lifts: Vec<Lift>,
+}
+
+impl Decks {
+    pub fn new<F>(count: usize, seed: Option<u64>, mut f: F) -> Decks
+    where
+        F: FnMut(Option<u64>) -> WaveStation
+    {
+        // initialize with either provided seed or actually random seed,
+        // the first deck always gets our seed so single decks are
+        // reproducible
+        let seed = seed.unwrap_or_else(|| {
+            rand::thread_rng().next_u64()
+        });
+        let mut prng = Xorshift64(seed);
+
+        let mut decks = vec![];
+        for i in 0..count {
+            decks.push(f(Some(if i == 0 { seed } else { prng.next_u64() })));
+        }
+
+        let mut self_ = Decks{
+            decks,
+            lifts: vec![],
+        };
+        self_.gen_lifts();
+        self_
+    }
+
+    // place lifts between any bubbles that overlap on adjacent decks,
+    // note this can be called after every chunk, we only add lifts to
+    // bubbles that don't already have them, and aren't frozen
+    pub fn gen_lifts(&mut self) {
+        for deck in 0..self.decks.len().saturating_sub(1) {
+            let (lower, upper) = self.decks.split_at_mut(deck+1);
+            let lower = &mut lower[deck];
+            let upper = &mut upper[0];
+            let scale = lower.scale as isize;
+
+            for a in lower.bubbles.clone() {
+                if a.borrow().frozen || self.lifts.iter().any(|lift| {
+                    lift.deck == deck && Rc::ptr_eq(&lift.a, &a)
+                }) {
+                    continue;
+                }
+
+                for b in upper.bubbles.clone() {
+                    if b.borrow().frozen || self.lifts.iter().any(|lift| {
+                        lift.deck == deck && Rc::ptr_eq(&lift.b, &b)
+                    }) {
+                        continue;
+                    }
+
+                    // find the midpoint between our bubbles in tiles
+                    let a_x = a.borrow().x * scale;
+                    let a_y = a.borrow().y * scale;
+                    let b_x = b.borrow().x * scale;
+                    let b_y = b.borrow().y * scale;
+                    let m_x = (a_x + b_x).div_euclid(2);
+                    let m_y = (a_y + b_y).div_euclid(2);
+
+                    // our lift needs to be comfortably inside both bubbles,
+                    // away from any walls
+                    let a_r = (a.borrow().r * scale as usize).saturating_sub(2);
+                    let b_r = (b.borrow().r * scale as usize).saturating_sub(2);
+                    if
+                        a_r == 0
+                            || b_r == 0
+                            || distsq((m_x, m_y), (a_x, a_y)) > sq(a_r)
+                            || distsq((m_x, m_y), (b_x, b_y)) > sq(b_r)
+                    {
+                        continue;
+                    }
+
+                    // pin matching lift tiles on both decks
+                    let a_off = (m_x - a_x, m_y - a_y);
+                    let b_off = (m_x - b_x, m_y - b_y);
+                    lower.pin(&a, a_off.0, a_off.1, TILE_LIFT);
+                    upper.pin(&b, b_off.0, b_off.1, TILE_LIFT);
+                    self.lifts.push(Lift{
+                        deck,
+                        a,
+                        b,
+                        a_off,
+                        b_off,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+
+    // remove any lifts connected to removed bubbles
+    fn remove_lifts(&mut self, deck: usize, removed: &[Rc<RefCell<Bubble>>]) {
+        let is_removed = |bubble: &Rc<RefCell<Bubble>>| {
+            removed.iter().any(|r| Rc::ptr_eq(r, bubble))
+        };
+
+        for lift in mem::take(&mut self.lifts) {
+            // unpin the other end of any lifts to removed bubbles
+            if lift.deck == deck && is_removed(&lift.a) {
+                self.decks[deck+1].unpin(&lift.b, lift.b_off.0, lift.b_off.1);
+            } else if lift.deck+1 == deck && is_removed(&lift.b) {
+                self.decks[deck-1].unpin(&lift.a, lift.a_off.0, lift.a_off.1);
+            } else {
+                self.lifts.push(lift);
+            }
+        }
+    }
+
+    // remove a bubble and its subtree from a deck, along with any lifts
+    pub fn remove_bubble(
+        &mut self,
+        deck: usize,
+        bubble: &Rc<RefCell<Bubble>>,
+    ) {
+        let removed = self.decks[deck].remove_bubble(bubble);
+        self.remove_lifts(deck, &removed);
+    }
+
+    // remove a hallway from a deck, along with any lifts
+    pub fn remove_hallway(
+        &mut self,
+        deck: usize,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) {
+        let removed = self.decks[deck].remove_hallway(a, b);
+        self.remove_lifts(deck, &removed);
+    }
+}
+
+// serialization
+impl Serialize for Decks {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S
+    ) -> Result<S::Ok, S::Error> {
+        // serialize lifts as bubble indices and tile positions on each deck
+        struct SerializeLifts<'a>(&'a [WaveStation], &'a [Lift]);
+
+        impl Serialize for SerializeLifts<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S
+            ) -> Result<S::Ok, S::Error> {
+                #[derive(Serialize)]
+                struct SerializeLift {
+                    deck: usize,
+                    bubbles: (usize, usize),
+                    tiles: ((isize, isize), (isize, isize)),
+                }
+
+                let bubble_maps = self.0.iter()
+                    .map(|ws| {
+                        ws.bubbles.iter()
+                            .enumerate()
+                            .map(|(i, bubble)| (Rc::as_ptr(bubble), i))
+                            .collect::<HashMap<_, _>>()
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut s = serializer.serialize_seq(Some(self.1.len()))?;
+                for lift in self.1 {
+                    // translate into each deck's bounding box
+                    let lower = &self.0[lift.deck];
+                    let upper = &self.0[lift.deck+1];
+                    s.serialize_element(&SerializeLift{
+                        deck: lift.deck,
+                        bubbles: (
+                            bubble_maps[lift.deck][&Rc::as_ptr(&lift.a)],
+                            bubble_maps[lift.deck+1][&Rc::as_ptr(&lift.b)],
+                        ),
+                        tiles: (
+                            (
+                                (lift.a.borrow().x - lower.lower_x)
+                                    * lower.scale as isize + lift.a_off.0,
+                                (lift.a.borrow().y - lower.lower_y)
+                                    * lower.scale as isize + lift.a_off.1,
+                            ),
+                            (
+                                (lift.b.borrow().x - upper.lower_x)
+                                    * upper.scale as isize + lift.b_off.0,
+                                (lift.b.borrow().y - upper.lower_y)
+                                    * upper.scale as isize + lift.b_off.1,
+                            ),
+                        ),
+                    })?;
+                }
+                s.end()
+            }
+        }
+
+        #[derive(Serialize)]
+        struct SerializeDecks<'a> {
+            decks: &'a [WaveStation],
+            lifts: SerializeLifts<'a>,
+        }
+
+        SerializeDecks{
+            decks: &self.decks,
+            lifts: SerializeLifts(&self.decks, &self.lifts),
+        }.serialize(serializer)
+    }
+}
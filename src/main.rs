@@ -2,6 +2,7 @@
 #![allow(unused_imports)]
 
 use structopt::StructOpt;
+use structopt::clap::AppSettings;
 use rand::{self, RngCore};
 use serde::{self, Serialize};
 use serde::ser::SerializeSeq;
@@ -11,7 +12,7 @@ use std::ops::Bound;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::cmp;
-use std::collections::{BTreeMap, HashMap, hash_map, btree_map};
+use std::collections::{BTreeMap, HashMap, HashSet, hash_map, btree_map};
 use std::io::{self, Write};
 use std::fs::File;
 use std::num;
@@ -19,6 +20,10 @@ use std::str::FromStr;
 use std::time::{Instant, Duration};
 use std::thread;
 use std::path::PathBuf;
+use std::mem;
+use std::env;
+use std::process;
+use std::borrow::Cow;
 
 mod constraints;
 use constraints::*;
@@ -26,9 +31,52 @@ use constraints::*;
 mod background_terminal;
 use background_terminal::*;
 
+mod mask;
+use mask::*;
+
+mod overlap;
+use overlap::*;
+
+mod zones;
+use zones::*;
+
+mod targets;
+use targets::*;
+
+mod tile_map;
+use tile_map::*;
+
+mod viewer;
+use viewer::*;
+
+mod raster;
+use raster::*;
+
+mod color;
+use color::*;
+
+mod report;
+use report::*;
+
+mod batch;
+use batch::*;
+
+mod adjacency;
+use adjacency::*;
+
+mod criteria;
+use criteria::*;
+
+mod decks;
+use decks::*;
+
+mod wfc;
+use wfc::*;
+
 
 //// prng stuff ////
 
+#[allow(clippy::four_forward_slashes)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Xorshift64(u64);
 
@@ -76,14 +124,25 @@ impl Xorshift64 {
 
 //// generate bubbles ////
 
+#[allow(clippy::four_forward_slashes)]
 #[derive(Debug, Clone)]
 struct Bubble {
     x: isize,
     y: isize,
     r: usize,
     parent: Option<Rc<RefCell<Bubble>>>,
+    zone: Option<Zone>,
+    // frozen bubbles have been seen, and must not change
+    frozen: bool,
+    // how many times we've re-solved this bubble to meet its target, and
+    // did it miss when we last checked?
+    target_tries: usize,
+    target_missed: bool,
 }
 
+// a hallway between two bubbles
+type Hallway = (Rc<RefCell<Bubble>>, Rc<RefCell<Bubble>>);
+
 fn sq(a: usize) -> f64 {
     a as f64 * a as f64
 }
@@ -113,28 +172,87 @@ struct WaveStation {
     // order is maintained so that parent bubbles always precedes a bubble
     // in the list
     //
-    // width/height effectively make a bounding box maintained around the
-    // bubbles
+    // the root bubble is always at 0,0, coordinates are signed and never
+    // change as we grow, lower_x/lower_y/width/height effectively make a
+    // bounding box maintained around the bubbles
     //
     size: usize,
+    lower_x: isize,
+    lower_y: isize,
     width: usize,
     height: usize,
     bubbles: Vec<Rc<RefCell<Bubble>>>,
 
-    // constraint map optionally generated by wave-function collapse
+    // list of station hallways
+    //
+    // this is the edge list of our bubble graph, hallways created by
+    // gen_bubbles are stored as (bubble, parent), followed by any extra
+    // hallways created to form loops
+    //
+    // note hallways run horizontally and vertically from the first
+    // bubble, so loops only connect bubbles that share a row or column
+    //
+    hallways: Vec<Hallway>,
+    loop_checked: usize,
+
+    // constraint map optionally generated by wave-function collapse,
+    // this is stored sparsely in tiles, cx/cy/cwidth/cheight cover our
+    // whole bounding box, but wfc only operates on a dense window copied
+    // out around whatever changed at wx/wy
+    tiles: TileMap,
+    cx: isize,
+    cy: isize,
     cwidth: usize,
     cheight: usize,
+    wx: isize,
+    wy: isize,
+    wwidth: usize,
+    wheight: usize,
     cmap: Vec<u128>,
+    zmap: Vec<Option<Zone>>,
     delta_bubbles: Vec<Rc<RefCell<Bubble>>>,
-    delta_hallways: Vec<Rc<RefCell<Bubble>>>,
+    delta_hallways: Vec<Hallway>,
+    delta_regions: Vec<(isize, isize, usize, usize)>,
+
+    // tiles pinned in place, these are stored relative to a bubble's
+    // center in tiles
+    pins: Vec<(Rc<RefCell<Bubble>>, isize, isize, u128)>,
+
+    // an optional point to grow toward, in cells relative to our root
+    // bubble
+    toward: Option<(isize, isize)>,
+    // frozen bubbles in tiles, these must not be marked during wfc
+    frozen: Vec<(isize, isize, usize)>,
 
     // generation config
     bubble_p: f64,
     hallway_p: f64,
     smallest: usize,
     clearance: usize,
+    loops: f64,
+    mask: Option<Mask>,
+    // learned patterns to fill interiors with, if any
+    overlap: Option<Overlap>,
+    zones: Option<ZoneMode>,
+    zone_depth: usize,
+    zone_p: f64,
     scale: usize,
     attempts: u64,
+    // solve wfc in independent regions with this many threads
+    wfc_threads: Option<usize>,
+    // limits on wfc, and what to do with anything left unresolved
+    wfc_budget: Budget,
+    wfc_fallback: Fallback,
+    // repair contradictions this many times before restarting
+    wfc_repair: usize,
+    wfc_propagator: Propagator,
+    // per-bubble room/door targets, and how many times to re-solve
+    // bubbles that miss them
+    targets: Vec<BubbleTarget>,
+    target_rerolls: usize,
+
+    // render config
+    charset: Charset,
 
     // stats
     bubble_cycles: u64,
@@ -142,19 +260,28 @@ struct WaveStation {
     wfc_attempts: u64,
     wfc_cycles: u64,
     wfc_propagations: u64,
+    wfc_repairs: u64,
     wfc_time: Duration,
+    wfc_chunks: Vec<WfcChunk>,
+    // did we run out of budget, how many tiles did we fill in, and how
+    // many neighbors did we fail to fill consistently?
+    wfc_exhausted: bool,
+    wfc_fallbacks: usize,
+    wfc_breaches: usize,
+    // bubbles we re-solved to meet targets
+    target_rerolled: usize,
 }
 
 impl WaveStation {
+    // note most of our config comes straight from our opts, anything that
+    // needs loading or depends on when we started is passed separately
     fn new(
         seed: Option<u64>,
         size: Option<usize>,
-        bubble_p: f64,
-        hallway_p: f64,
-        smallest: usize,
-        clearance: usize,
-        scale: usize,
-        attempts: u64,
+        opt: &Opt,
+        mask: Option<Mask>,
+        overlap: Option<Overlap>,
+        wfc_budget: Budget,
     ) -> WaveStation {
         // initialize with either provided seed or actually random seed
         let seed = seed.unwrap_or_else(|| {
@@ -162,42 +289,94 @@ impl WaveStation {
         });
 
         let mut self_ = WaveStation{
-            seed: seed,
+            seed,
             prng: Xorshift64(seed),
 
             size: 0,
+            lower_x: 0,
+            lower_y: 0,
             width: 0,
             height: 0,
             // initialize with one bubble of a random size
             bubbles: vec![],
+            hallways: vec![],
+            loop_checked: 0,
 
+            tiles: TileMap::new(),
+            cx: 0,
+            cy: 0,
             cwidth: 0,
             cheight: 0,
+            wx: 0,
+            wy: 0,
+            wwidth: 0,
+            wheight: 0,
             cmap: vec![],
+            zmap: vec![],
             delta_bubbles: vec![],
             delta_hallways: vec![],
-
-            bubble_p: bubble_p,
-            hallway_p: hallway_p,
-            smallest: smallest,
-            clearance: clearance,
-            scale: scale,
-            attempts: attempts,
+            delta_regions: vec![],
+
+            pins: vec![],
+
+            toward: None,
+            frozen: vec![],
+
+            bubble_p: opt.bubble_p,
+            hallway_p: opt.hallway_p,
+            smallest: opt.smallest,
+            clearance: opt.clearance,
+            loops: opt.loops,
+            mask,
+            overlap,
+            zones: opt.zones,
+            zone_depth: opt.zone_depth,
+            zone_p: opt.zone_p,
+            scale: opt.scale,
+            attempts: opt.attempts,
+            wfc_threads: opt.wfc_threads,
+            wfc_budget,
+            wfc_fallback: opt.wfc_fallback,
+            wfc_repair: opt.wfc_repair,
+            wfc_propagator: opt.propagator,
+            targets: opt.bubble_target.clone(),
+            target_rerolls: opt.target_rerolls,
+
+            charset: opt.charset,
 
             bubble_cycles: 0,
             bubble_time: Duration::ZERO,
             wfc_attempts: 0,
             wfc_cycles: 0,
             wfc_propagations: 0,
+            wfc_repairs: 0,
             wfc_time: Duration::ZERO,
+            wfc_chunks: vec![],
+            wfc_exhausted: false,
+            wfc_fallbacks: 0,
+            wfc_breaches: 0,
+            target_rerolled: 0,
         };
 
         // initialize with one bubble of a random size
+        //
+        // if we have a mask, shrink our bubble to fit
+        let mut r = opt.smallest + self_.prng.poisson(opt.bubble_p);
+        if let Some(mask) = &self_.mask {
+            while r > opt.smallest && !mask.contains(0, 0, r) {
+                r -= 1;
+            }
+        }
         let bubble = Rc::new(RefCell::new(Bubble{
             x: 0,
             y: 0,
-            r: smallest + self_.prng.poisson(bubble_p),
+            r,
             parent: None,
+            // the root bubble is always habitable
+            zone: opt.zones.map(|_| Zone::Habitation),
+            frozen: false,
+            target_tries: 0,
+            target_missed: false,
         }));
         self_.bubbles.push(Rc::clone(&bubble));
         self_.size += bubble.borrow().r;
@@ -209,81 +388,646 @@ impl WaveStation {
             }
         }
 
-        self_.center();
+        self_.update_bounds();
         self_
     }
 
-    fn gen_bubbles(&mut self, delta: usize) {
+    // returns false if we gave up before reaching the requested size,
+    // which can happen if our mask fills up
+    fn gen_bubbles(&mut self, delta: usize) -> bool {
         let start = Instant::now();
 
         let size = self.size + delta;
+        let mut fails = 0;
         while self.size < size {
+            // give up if we keep failing with a mask, it may be full, without
+            // a mask there's always room to grow somewhere
+            if
+                self.mask.is_some()
+                    && fails > cmp::max(1000, 10*self.bubbles.len())
+            {
+                break;
+            }
+
             self.bubble_cycles += 1;
-            // choose a bubble
-            let parent = &self.bubbles[self.prng.range(0..self.bubbles.len())];
-            // choose a direction
-            let (dir_x, dir_y) = match self.prng.range(0..4) {
-                0 => (0, 1),
-                1 => (1, 0),
-                2 => (0, -1),
-                3 => (-1, 0),
-                _ => unreachable!(),
+            let (parent, dir) = match self.toward {
+                None => {
+                    // choose a bubble
+                    let parent = Rc::clone(
+                        &self.bubbles[self.prng.range(0..self.bubbles.len())]
+                    );
+                    // choose a direction
+                    let dir = self.prng.range(0..4);
+                    (parent, dir)
+                }
+                Some(toward) => match self.choose_toward(toward) {
+                    Some(choice) => choice,
+                    // nothing left to grow from?
+                    None => break,
+                },
             };
             // choose a size
             let r = self.smallest + self.prng.poisson(self.bubble_p);
 
             // calculate new position
+            //
+            // if we have a mask, try each direction in turn, this biases
+            // generation towards filling the mask instead of bouncing off
+            // its edges
             let hallway = self.clearance + self.prng.poisson(self.hallway_p);
-            let x = parent.borrow().x
-                + dir_x*((parent.borrow().r + r + hallway) as isize);
-            let y = parent.borrow().y
-                + dir_y*((parent.borrow().r + r + hallway) as isize);
-
-            // but wait, is there a collision?
-            let mut collision = false;
-            for bubble in &self.bubbles {
-                if Rc::ptr_eq(&bubble, parent) {
+            let mut found = None;
+            for i in 0..if self.mask.is_some() { 4 } else { 1 } {
+                let (dir_x, dir_y) = match (dir+i) % 4 {
+                    0 => (0, 1),
+                    1 => (1, 0),
+                    2 => (0, -1),
+                    3 => (-1, 0),
+                    _ => unreachable!(),
+                };
+                let x = parent.borrow().x
+                    + dir_x*((parent.borrow().r + r + hallway) as isize);
+                let y = parent.borrow().y
+                    + dir_y*((parent.borrow().r + r + hallway) as isize);
+
+                // are we in our mask?
+                if let Some(mask) = &self.mask {
+                    if !mask.contains(x, y, r) {
+                        continue;
+                    }
+
+                    // our hallway also needs to stay in our mask
+                    let p_x = parent.borrow().x;
+                    let p_y = parent.borrow().y;
+                    if
+                        (cmp::min(x, p_x) ..= cmp::max(x, p_x))
+                            .flat_map(|x_| {
+                                (cmp::min(y, p_y) ..= cmp::max(y, p_y))
+                                    .map(move |y_| (x_, y_))
+                            })
+                            .any(|(x_, y_)| {
+                                mask.dist(x_, y_) > 0.0
+                            })
+                    {
+                        continue;
+                    }
+                }
+
+                // but wait, is there a collision?
+                let mut collision = false;
+                for bubble in &self.bubbles {
+                    if Rc::ptr_eq(bubble, &parent) {
+                        continue;
+                    }
+
+                    // check bubble collision
+                    if
+                        distsq((x, y), (bubble.borrow().x, bubble.borrow().y))
+                            <= sq(r + bubble.borrow().r + self.clearance)
+                    {
+                        collision = true;
+                        break;
+                    }
+                }
+                if collision {
                     continue;
                 }
 
-                // check bubble collision
-                if
-                    distsq((x, y), (bubble.borrow().x, bubble.borrow().y))
-                        <= sq(r + bubble.borrow().r + self.clearance)
-                {
-                    collision = true;
-                    break;
+                // our hallway can't cut through any frozen bubbles either
+                if self.bubbles.iter().any(|bubble| {
+                    bubble.borrow().frozen
+                        && Self::hallway_points(
+                            (x, y),
+                            (parent.borrow().x, parent.borrow().y),
+                        ).into_iter().any(|(x_, y_)| {
+                            distsq(
+                                (x_, y_),
+                                (bubble.borrow().x, bubble.borrow().y)
+                            ) <= sq(bubble.borrow().r)
+                        })
+                }) {
+                    continue;
                 }
-            }
-            if collision {
-                continue;
+
+                found = Some((x, y));
+                break;
             }
 
+            let (x, y) = match found {
+                Some(found) => found,
+                None => {
+                    fails += 1;
+                    continue;
+                }
+            };
+            fails = 0;
+
+            // choose a zone
+            let zone = match self.zones {
+                None => None,
+                Some(ZoneMode::Depth) => {
+                    let mut depth = 1;
+                    let mut parent_ = parent.borrow().parent.clone();
+                    while let Some(p) = parent_ {
+                        depth += 1;
+                        parent_ = p.borrow().parent.clone();
+                    }
+                    Some(Zone::ALL[
+                        (depth / cmp::max(self.zone_depth, 1))
+                            % Zone::ALL.len()
+                    ])
+                }
+                Some(ZoneMode::Cluster) => {
+                    if self.prng.bool(self.zone_p) {
+                        Some(Zone::ALL[self.prng.range(0..Zone::ALL.len())])
+                    } else {
+                        parent.borrow().zone
+                    }
+                }
+            };
+
             // no? ok add to our bubbles
             let bubble = Rc::new(RefCell::new(Bubble{
-                x: x,
-                y: y,
-                r: r,
-                parent: Some(Rc::clone(parent)),
+                x,
+                y,
+                r,
+                parent: Some(Rc::clone(&parent)),
+                zone,
+                frozen: false,
+                target_tries: 0,
+                target_missed: false,
             }));
             self.bubbles.push(Rc::clone(&bubble));
+            self.hallways.push((Rc::clone(&bubble), Rc::clone(&parent)));
             self.size += r;
 
             // keep track of new bubbles/hallways that need an update if we
             // have an existing constraint map
-            self.delta_bubble(&bubble);
+            self.delta_hallway(&bubble, &parent);
         }
 
-        self.center();
+        // add any loops
+        self.gen_loops();
+
+        self.update_bounds();
 
         let stop = Instant::now();
         self.bubble_time += stop.duration_since(start);
+
+        self.size >= size
     }
 
-    fn delta_bubble(&mut self, bubble: &Rc<RefCell<Bubble>>) {
+    // choose a parent bubble and direction that gets us closer to a
+    // point, this is still random, just biased toward the point
+    fn choose_toward(
+        &mut self,
+        (t_x, t_y): (isize, isize),
+    ) -> Option<(Rc<RefCell<Bubble>>, usize)> {
+        // choose one of the few bubbles closest to our point
+        let mut candidates = self.bubbles.iter()
+            .filter(|bubble| !bubble.borrow().frozen)
+            .map(|bubble| (
+                distsq((bubble.borrow().x, bubble.borrow().y), (t_x, t_y)),
+                Rc::clone(bubble),
+            ))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        candidates.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        let i = self.prng.range(0..cmp::min(4, candidates.len()));
+        let parent = Rc::clone(&candidates[i].1);
+
+        // usually grow toward our point, but not always
+        let d_x = t_x - parent.borrow().x;
+        let d_y = t_y - parent.borrow().y;
+        let dir = if self.prng.bool(0.25) {
+            self.prng.range(0..4)
+        } else if d_y.abs() >= d_x.abs() {
+            if d_y >= 0 { 0 } else { 2 }
+        } else {
+            if d_x >= 0 { 1 } else { 3 }
+        };
+
+        Some((parent, dir))
+    }
+
+    // distance from a point to the nearest bubble in cells, the point is
+    // in tiles relative to our root bubble, 0 if the point is in a bubble
+    fn dist_toward(&self, (x, y): (isize, isize)) -> f64 {
+        let t_x = x.div_euclid(self.scale as isize);
+        let t_y = y.div_euclid(self.scale as isize);
+        self.bubbles.iter()
+            .map(|bubble| {
+                (
+                    dist((bubble.borrow().x, bubble.borrow().y), (t_x, t_y))
+                        - bubble.borrow().r as f64
+                ).max(0.0)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    // have we reached a point? bubbles need clearance, so we can't always
+    // land exactly on a point, but we can get close
+    fn reached(&self, point: (isize, isize)) -> bool {
+        self.dist_toward(point) <= (self.clearance + 1) as f64
+    }
+
+    // freeze any bubbles within view tiles of a point, in tiles relative
+    // to our root bubble, this keeps any resolved tiles there fixed as we
+    // continue to grow
+    fn freeze(&mut self, (x, y): (isize, isize), view: usize) {
+        // only freeze bubbles that have actually been resolved
+        if self.tiles.is_empty() {
+            return;
+        }
+
+        for bubble in &self.bubbles {
+            let b_x = bubble.borrow().x * self.scale as isize;
+            let b_y = bubble.borrow().y * self.scale as isize;
+            let r = bubble.borrow().r * self.scale;
+            if dist((b_x, b_y), (x, y)) <= (r + view) as f64 {
+                bubble.borrow_mut().frozen = true;
+            }
+        }
+    }
+
+    // grow toward a point, in tiles relative to our root bubble
+    //
+    // returns false if we gave up before reaching the requested size
+    fn gen_toward(&mut self, (x, y): (isize, isize), delta: usize) -> bool {
+        self.toward = Some((
+            x.div_euclid(self.scale as isize),
+            y.div_euclid(self.scale as isize),
+        ));
+        let success = self.gen_bubbles(delta);
+        self.toward = None;
+        success
+    }
+
+    // find the path a hallway takes, hallways run horizontally and then
+    // vertically from the first bubble
+    fn hallway_path(
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) -> Vec<(isize, isize)> {
+        Self::hallway_points(
+            (a.borrow().x, a.borrow().y),
+            (b.borrow().x, b.borrow().y),
+        )
+    }
+
+    fn hallway_points(
+        (a_x, a_y): (isize, isize),
+        (b_x, b_y): (isize, isize),
+    ) -> Vec<(isize, isize)> {
+        let mut path = vec![];
+        for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
+            path.push((a_x_, a_y));
+        }
+        for a_y_ in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
+            if a_y_ != a_y {
+                path.push((a_x, a_y_));
+            }
+        }
+        path
+    }
+
+    // are two bubbles already connected by a hallway?
+    fn connected(
+        &self,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) -> bool {
+        self.hallways.iter().any(|(a_, b_)| {
+            (Rc::ptr_eq(a, a_) && Rc::ptr_eq(b, b_))
+                || (Rc::ptr_eq(a, b_) && Rc::ptr_eq(b, a_))
+        })
+    }
+
+    // can we fit a hallway between two bubbles without running into
+    // other bubbles or hallways?
+    fn hallway_clear(
+        &self,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) -> bool {
+        // only consider the part of our hallway outside of our bubbles
+        let path = Self::hallway_path(a, b).into_iter()
+            .filter(|&(x, y)| {
+                distsq((x, y), (a.borrow().x, a.borrow().y))
+                    > sq(a.borrow().r)
+                && distsq((x, y), (b.borrow().x, b.borrow().y))
+                    > sq(b.borrow().r)
+            })
+            .collect::<Vec<_>>();
+
+        // check bubble collision
+        for bubble in &self.bubbles {
+            if Rc::ptr_eq(bubble, a) || Rc::ptr_eq(bubble, b) {
+                continue;
+            }
+
+            let x = bubble.borrow().x;
+            let y = bubble.borrow().y;
+            let r = bubble.borrow().r;
+            for &(x_, y_) in &path {
+                if distsq((x_, y_), (x, y)) <= sq(r + self.clearance) {
+                    return false;
+                }
+            }
+        }
+
+        // check hallway collision, note we don't allow hallways to touch
+        // since this creates messy intersections for wfc
+        for (a_, b_) in &self.hallways {
+            for (x, y) in Self::hallway_path(a_, b_) {
+                for &(x_, y_) in &path {
+                    if (x_-x).abs() <= 1 && (y_-y).abs() <= 1 {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    fn gen_loops(&mut self) {
+        // how many loops do we want?
+        //
+        // note a tree always has exactly one less hallway than bubbles,
+        // anything more is a loop
+        let mut loops = self.hallways.len() + 1 - self.bubbles.len();
+        let target = (
+            self.loops * (self.bubbles.len()-1) as f64
+        ) as usize;
+        if loops >= target {
+            return;
+        }
+
+        // find the longest hallway we have, we only want to add loops
+        // between nearby bubbles
+        let mut longest = 0;
+        for (a, b) in &self.hallways {
+            let len = (a.borrow().x - b.borrow().x).abs()
+                + (a.borrow().y - b.borrow().y).abs()
+                - a.borrow().r as isize
+                - b.borrow().r as isize;
+            longest = cmp::max(longest, len);
+        }
+
+        // find candidate loops, we only need to look at bubbles we
+        // haven't looked at before
+        let mut candidates = vec![];
+        for (j, b) in self.bubbles.iter().enumerate().skip(self.loop_checked) {
+            for a in &self.bubbles[..j] {
+                // our hallways only reach bubbles in the same row or
+                // column
+                if
+                    a.borrow().x != b.borrow().x
+                        && a.borrow().y != b.borrow().y
+                {
+                    continue;
+                }
+
+                // too far?
+                let len = (a.borrow().x - b.borrow().x).abs()
+                    + (a.borrow().y - b.borrow().y).abs();
+                if
+                    len - a.borrow().r as isize - b.borrow().r as isize
+                        > longest
+                {
+                    continue;
+                }
+
+                // already connected? or frozen in place?
+                if
+                    self.connected(a, b)
+                        || a.borrow().frozen
+                        || b.borrow().frozen
+                {
+                    continue;
+                }
+
+                candidates.push((len, Rc::clone(a), Rc::clone(b)));
+            }
+        }
+        self.loop_checked = self.bubbles.len();
+
+        // prefer shorter loops
+        candidates.sort_by_key(|(len, _, _)| *len);
+
+        while loops < target && !candidates.is_empty() {
+            self.bubble_cycles += 1;
+            // choose a loop
+            let i = cmp::min(
+                self.prng.poisson(self.hallway_p),
+                candidates.len()-1
+            );
+            let (_, a, b) = candidates.remove(i);
+
+            // but wait, did we already connect these? is there a collision?
+            if self.connected(&a, &b) || !self.hallway_clear(&a, &b) {
+                continue;
+            }
+
+            // no? ok add to our hallways
+            self.hallways.push((Rc::clone(&a), Rc::clone(&b)));
+            loops += 1;
+
+            // keep track of new bubbles/hallways that need an update if we
+            // have an existing constraint map
+            self.delta_hallway(&a, &b);
+        }
+    }
+
+    // pin a tile relative to a bubble's center, this forces the tile
+    // during wfc, and may mean the bubble needs to be resolved again
+    fn pin(
+        &mut self,
+        bubble: &Rc<RefCell<Bubble>>,
+        x: isize,
+        y: isize,
+        tile: u128,
+    ) {
+        self.pins.push((Rc::clone(bubble), x, y, tile));
+        self.delta_bubble(bubble);
+    }
+
+    // remove a pinned tile, letting wfc choose the tile again
+    fn unpin(&mut self, bubble: &Rc<RefCell<Bubble>>, x: isize, y: isize) {
+        self.pins.retain(|(bubble_, x_, y_, _)| {
+            !(Rc::ptr_eq(bubble_, bubble) && *x_ == x && *y_ == y)
+        });
+        self.delta_bubble(bubble);
+    }
+
+    // remove a bubble and its subtree, along with any hallways connected
+    // to them, returning the removed bubbles
+    //
+    // note the root bubble can't be removed
+    //
+    fn remove_bubble(
+        &mut self,
+        bubble: &Rc<RefCell<Bubble>>
+    ) -> Vec<Rc<RefCell<Bubble>>> {
+        if Rc::ptr_eq(bubble, &self.bubbles[0]) {
+            return vec![];
+        }
+
+        // find our subtree, parents always precede their children so this
+        // only takes one pass
+        let mut removed: Vec<Rc<RefCell<Bubble>>> = vec![];
+        for bubble_ in &self.bubbles {
+            if
+                Rc::ptr_eq(bubble_, bubble)
+                    || bubble_.borrow().parent.as_ref().is_some_and(|p| {
+                        removed.iter().any(|r| Rc::ptr_eq(r, p))
+                    })
+            {
+                removed.push(Rc::clone(bubble_));
+            }
+        }
+        let is_removed = |bubble: &Rc<RefCell<Bubble>>| {
+            removed.iter().any(|r| Rc::ptr_eq(r, bubble))
+        };
+
+        // find connected hallways
+        let (hallways, kept) = mem::take(&mut self.hallways)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(a, b)| is_removed(a) || is_removed(b));
+        self.hallways = kept;
+
+        // and remove everything else
+        self.loop_checked -= self.bubbles[..self.loop_checked].iter()
+            .filter(|bubble| is_removed(bubble))
+            .count();
+        self.bubbles.retain(|bubble| !is_removed(bubble));
+        self.pins.retain(|(bubble, _, _, _)| !is_removed(bubble));
+        self.delta_bubbles.retain(|bubble| !is_removed(bubble));
+        self.delta_hallways.retain(|(a, b)| !is_removed(a) && !is_removed(b));
+        for bubble in &removed {
+            self.size -= bubble.borrow().r;
+        }
+
+        self.clear(&removed, &hallways);
+        self.update_bounds();
+        removed
+    }
+
+    // remove a hallway, returning any removed bubbles
+    //
+    // loops can be removed on their own, but removing a hallway in our
+    // tree would disconnect the station, so this removes the child's
+    // subtree instead
+    //
+    fn remove_hallway(
+        &mut self,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) -> Vec<Rc<RefCell<Bubble>>> {
+        let is_parent = |a: &Rc<RefCell<Bubble>>, b: &Rc<RefCell<Bubble>>| {
+            a.borrow().parent.as_ref().is_some_and(|p| Rc::ptr_eq(p, b))
+        };
+        if is_parent(a, b) {
+            return self.remove_bubble(a);
+        } else if is_parent(b, a) {
+            return self.remove_bubble(b);
+        }
+
+        self.hallways.retain(|(a_, b_)| {
+            !(Rc::ptr_eq(a_, a) && Rc::ptr_eq(b_, b))
+        });
+        self.delta_hallways.retain(|(a_, b_)| {
+            !(Rc::ptr_eq(a_, a) && Rc::ptr_eq(b_, b))
+        });
+        self.clear(&[], &[(Rc::clone(a), Rc::clone(b))]);
+        vec![]
+    }
+
+    // clear the tiles under removed bubbles/hallways back to space, and
+    // mark anything touching them to be resolved again
+    fn clear(
+        &mut self,
+        bubbles: &[Rc<RefCell<Bubble>>],
+        hallways: &[Hallway],
+    ) {
+        // do nothing if we have no constraint map
+        if self.tiles.is_empty() {
+            return;
+        }
+
+        let mut cleared = HashSet::new();
+        for bubble in bubbles {
+            cleared.extend(self.bubble_tiles(bubble));
+        }
+        for (a, b) in hallways {
+            cleared.extend(self.hallway_tiles(a, b));
+        }
+        for &(x, y) in &cleared {
+            self.tiles.set(x, y, TILE_SPACE, None);
+        }
+
+        let touches = |tiles: Vec<(isize, isize)>| {
+            tiles.into_iter().any(|(x, y)| {
+                (-1..=1).any(|d_y| {
+                    (-1..=1).any(|d_x| cleared.contains(&(x+d_x, y+d_y)))
+                })
+            })
+        };
+
+        // anything at the other end of a removed hallway, or touching
+        // removed tiles, needs to be resolved again
+        for bubble in self.bubbles.clone() {
+            if
+                hallways.iter().any(|(a, b)| {
+                    Rc::ptr_eq(a, &bubble) || Rc::ptr_eq(b, &bubble)
+                })
+                    || touches(self.bubble_tiles(&bubble))
+            {
+                bubble.borrow_mut().frozen = false;
+                self.delta_bubble(&bubble);
+            }
+        }
+        for (a, b) in self.hallways.clone() {
+            if touches(self.hallway_tiles(&a, &b)) {
+                self.delta_hallways.push((a, b));
+            }
+        }
+    }
+
+    // resolve a bubble's tiles again, keeping its surroundings fixed
+    fn reroll_bubble(&mut self, bubble: &Rc<RefCell<Bubble>>) {
+        bubble.borrow_mut().frozen = false;
+        self.delta_bubble(bubble);
+    }
+
+    // resolve any tiles inside our station in a region again, keeping
+    // its surroundings fixed, the region is in tiles relative to our root
+    // bubble
+    fn reroll_region(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+    ) {
+        // do nothing if we have no constraint map
+        if self.tiles.is_empty() {
+            return;
+        }
+
+        self.delta_regions.push((x, y, width, height));
+    }
+
+    fn delta_hallway(
+        &mut self,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) {
         // do nothing if we have no constraint map, we'll just update all
         // bubbles in this case
-        if self.cmap.len() == 0 {
+        if self.tiles.is_empty() {
             return;
         }
 
@@ -297,14 +1041,14 @@ impl WaveStation {
         // 4. any bubbles their hallway collides with
 
         // collect any bubbles our new hallway collides with, this
-        // should include our new bubble and our parent, but may include
+        // should include both ends of our hallway, but may include
         // more bubbles
-        let delta_i = self.delta_bubbles.len();
-        if let Some(parent) = &bubble.borrow().parent {
-            let a_x = bubble.borrow().x;
-            let a_y = bubble.borrow().y;
-            let b_x = parent.borrow().x;
-            let b_y = parent.borrow().y;
+        let mut collisions = vec![];
+        {
+            let a_x = a.borrow().x;
+            let a_y = a.borrow().y;
+            let b_x = b.borrow().x;
+            let b_y = b.borrow().y;
             for bubble_ in &self.bubbles {
                 let x = bubble_.borrow().x;
                 let y = bubble_.borrow().y;
@@ -318,8 +1062,8 @@ impl WaveStation {
                 for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
                     if
                         distsq(
-                            (x as isize, y as isize),
-                            (a_x_ as isize, a_y as isize)
+                            (x, y),
+                            (a_x_, a_y)
                         ) <= sq(r)
                     {
                         collides = true;
@@ -329,8 +1073,8 @@ impl WaveStation {
                 for a_y_ in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
                     if
                         distsq(
-                            (x as isize, y as isize),
-                            (a_x as isize, a_y_ as isize)
+                            (x, y),
+                            (a_x, a_y_)
                         ) <= sq(r)
                     {
                         collides = true;
@@ -339,64 +1083,75 @@ impl WaveStation {
                 }
 
                 if collides {
-                    self.delta_bubbles.push(Rc::clone(bubble_));
+                    collisions.push(Rc::clone(bubble_));
                 }
             }
         }
 
-        // collect any hallways our bubbles collide with
+        for bubble in &collisions {
+            self.delta_bubble(bubble);
+        }
+    }
+
+    fn delta_bubble(&mut self, bubble: &Rc<RefCell<Bubble>>) {
+        // do nothing if we have no constraint map, we'll just update all
+        // bubbles in this case
+        if self.tiles.is_empty() {
+            return;
+        }
+
+        self.delta_bubbles.push(Rc::clone(bubble));
+
+        // collect any hallways our bubble collides with
         //
-        // note we store this as the destination bubble so we can take
+        // note we store this as the bubbles at either end so we can take
         // advantage of automatic updates when centering
-
-        for bubble in &self.delta_bubbles[delta_i..] {
+        {
             let x = bubble.borrow().x;
             let y = bubble.borrow().y;
             let r = bubble.borrow().r;
-            for bubble_ in &self.bubbles {
-                if let Some(parent) = &bubble_.borrow().parent {
-                    let a_x = bubble_.borrow().x;
-                    let a_y = bubble_.borrow().y;
-                    let b_x = parent.borrow().x;
-                    let b_y = parent.borrow().y;
-                    let mut collides = false;
-
-                    // naive line/circle collision detection
-                    //
-                    // this could be made faster with a bit of math,
-                    // but math is hard
-                    for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
-                        if
-                            distsq(
-                                (x as isize, y as isize),
-                                (a_x_ as isize, a_y as isize)
-                            ) <= sq(r)
-                        {
-                            collides = true;
-                            break;
-                        }
+            for (a, b) in &self.hallways {
+                let a_x = a.borrow().x;
+                let a_y = a.borrow().y;
+                let b_x = b.borrow().x;
+                let b_y = b.borrow().y;
+                let mut collides = false;
+
+                // naive line/circle collision detection
+                //
+                // this could be made faster with a bit of math,
+                // but math is hard
+                for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
+                    if
+                        distsq(
+                            (x, y),
+                            (a_x_, a_y)
+                        ) <= sq(r)
+                    {
+                        collides = true;
+                        break;
                     }
-                    for a_y_ in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
-                        if
-                            distsq(
-                                (x as isize, y as isize),
-                                (a_x as isize, a_y_ as isize)
-                            ) <= sq(r)
-                        {
-                            collides = true;
-                            break;
-                        }
+                }
+                for a_y_ in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
+                    if
+                        distsq(
+                            (x, y),
+                            (a_x, a_y_)
+                        ) <= sq(r)
+                    {
+                        collides = true;
+                        break;
                     }
+                }
 
-                    if collides {
-                        self.delta_hallways.push(Rc::clone(bubble_));
-                    }
+                if collides {
+                    self.delta_hallways.push((Rc::clone(a), Rc::clone(b)));
                 }
             }
         }
     }
 
-    fn center(&mut self) {
+    fn update_bounds(&mut self) {
         // find bounds
         let (mut lower_x, mut lower_y) = (0, 0);
         let (mut upper_x, mut upper_y) = (1, 1);
@@ -414,38 +1169,10 @@ impl WaveStation {
                 upper_y, bubble.borrow().y+bubble.borrow().r as isize
             );
         }
+        self.lower_x = lower_x;
+        self.lower_y = lower_y;
         self.width = (upper_x+1 - lower_x) as usize;
         self.height = (upper_y+1 - lower_y) as usize;
-
-        // shift all bubbles into the range 0,0 => width,height
-        for bubble in &self.bubbles {
-            bubble.borrow_mut().x -= lower_x;
-            bubble.borrow_mut().y -= lower_y;
-        }
-
-        if self.cmap.len() > 0 {
-            // adjust our constraint map as necessary, filling
-            // in uninitialized constraints with space for now
-            let cwidth = self.width*self.scale;
-            let cheight = self.height*self.scale;
-            let mut cmap = vec![TILE_SPACE; cwidth*cheight];
-
-            debug_assert!(lower_x < 0);
-            debug_assert!(lower_y < 0);
-            for y in 0..self.cheight {
-                for x in 0..self.cwidth {
-                    cmap[
-                        (x as isize-lower_x*self.scale as isize) as usize
-                        + (y as isize-lower_y*self.scale as isize) as usize
-                            *cwidth
-                    ] = self.cmap[x+y*self.cwidth];
-                }
-            }
-
-            self.cwidth = cwidth;
-            self.cheight = cheight;
-            self.cmap = cmap;
-        }
     }
 
     // render small map at a requested size
@@ -453,53 +1180,63 @@ impl WaveStation {
         &self,
         swidth: usize,
         sheight: usize,
-    ) -> (usize, usize, Vec<u8>) {
-        let mut smap = vec![b' '; swidth*sheight];
+    ) -> (usize, usize, Vec<char>) {
+        let mut smap = vec![' '; swidth*sheight];
         let scale_x = swidth as f64 / self.width as f64;
         let scale_y = sheight as f64 / self.height as f64;
 
         // show hallways
-        for bubble in &self.bubbles {
-            let x = (bubble.borrow().x as f64 * scale_x) as usize;
-            let y = (bubble.borrow().y as f64 * scale_y) as usize;
-            if let Some(parent) = &bubble.borrow().parent {
-                let p_x = (parent.borrow().x as f64 * scale_x) as usize;
-                let p_y = (parent.borrow().y as f64 * scale_y) as usize;
-                for x_ in cmp::min(x, p_x) ..= cmp::max(x, p_x) {
-                    if smap[x_+y*swidth] == b'|' {
-                        smap[x_+y*swidth] = b'+';
-                    } else {
-                        smap[x_+y*swidth] = b'-';
-                    }
+        for (a, b) in &self.hallways {
+            let x = (
+                (a.borrow().x - self.lower_x) as f64 * scale_x
+            ) as usize;
+            let y = (
+                (a.borrow().y - self.lower_y) as f64 * scale_y
+            ) as usize;
+            let p_x = (
+                (b.borrow().x - self.lower_x) as f64 * scale_x
+            ) as usize;
+            let p_y = (
+                (b.borrow().y - self.lower_y) as f64 * scale_y
+            ) as usize;
+            for x_ in cmp::min(x, p_x) ..= cmp::max(x, p_x) {
+                if smap[x_+y*swidth] == '|' {
+                    smap[x_+y*swidth] = '+';
+                } else {
+                    smap[x_+y*swidth] = '-';
                 }
-                for y_ in cmp::min(y, p_y) ..= cmp::max(y, p_y) {
-                    if smap[x+y_*swidth] == b'-' {
-                        smap[x+y_*swidth] = b'+';
-                    } else {
-                        smap[x+y_*swidth] = b'|';
-                    }
+            }
+            for y_ in cmp::min(y, p_y) ..= cmp::max(y, p_y) {
+                if smap[x+y_*swidth] == '-' {
+                    smap[x+y_*swidth] = '+';
+                } else {
+                    smap[x+y_*swidth] = '|';
                 }
             }
         }
 
         // show bubbles
         for bubble in &self.bubbles {
-            let x = (bubble.borrow().x as f64 * scale_x) as usize;
-            let y = (bubble.borrow().y as f64 * scale_y) as usize;
-            smap[x+y*swidth] = b'o';
+            let x = (
+                (bubble.borrow().x - self.lower_x) as f64 * scale_x
+            ) as usize;
+            let y = (
+                (bubble.borrow().y - self.lower_y) as f64 * scale_y
+            ) as usize;
+            smap[x+y*swidth] = 'o';
         }
 
         (swidth, sheight, smap)
     }
 
     // render bubble map
-    fn render_bubble_map(&self) -> (usize, usize, Vec<u8>) {
-        let mut bmap = vec![b' '; self.width*self.height];
+    fn render_bubble_map(&self) -> (usize, usize, Vec<char>) {
+        let mut bmap = vec![' '; self.width*self.height];
 
         // show bubbles
         for bubble in &self.bubbles {
-            let x = bubble.borrow().x as usize;
-            let y = bubble.borrow().y as usize;
+            let x = (bubble.borrow().x - self.lower_x) as usize;
+            let y = (bubble.borrow().y - self.lower_y) as usize;
             let r = bubble.borrow().r;
             for y_ in 0..self.height {
                 for x_ in 0..self.width {
@@ -509,130 +1246,188 @@ impl WaveStation {
                             (x as isize, y as isize)
                         ) <= sq(r)
                     {
-                        bmap[x_+y_*self.width] = b'.';
+                        bmap[x_+y_*self.width] = bubble.borrow().zone
+                            .map_or('.', |zone| zone.ascii() as char);
                     }
                 }
             }
         }
 
         // show hallways
-        for bubble in &self.bubbles {
-            let x = bubble.borrow().x as usize;
-            let y = bubble.borrow().y as usize;
-            if let Some(parent) = &bubble.borrow().parent {
-                let p_x = parent.borrow().x as usize;
-                let p_y = parent.borrow().y as usize;
-                for x_ in cmp::min(x, p_x) ..= cmp::max(x, p_x) {
-                    if bmap[x_+y*self.width] == b'|' {
-                        bmap[x_+y*self.width] = b'+';
-                    } else {
-                        bmap[x_+y*self.width] = b'-';
-                    }
+        for (a, b) in &self.hallways {
+            let x = (a.borrow().x - self.lower_x) as usize;
+            let y = (a.borrow().y - self.lower_y) as usize;
+            let p_x = (b.borrow().x - self.lower_x) as usize;
+            let p_y = (b.borrow().y - self.lower_y) as usize;
+            for x_ in cmp::min(x, p_x) ..= cmp::max(x, p_x) {
+                if bmap[x_+y*self.width] == '|' {
+                    bmap[x_+y*self.width] = '+';
+                } else {
+                    bmap[x_+y*self.width] = '-';
                 }
-                for y_ in cmp::min(y, p_y) ..= cmp::max(y, p_y) {
-                    if bmap[x+y_*self.width] == b'-' {
-                        bmap[x+y_*self.width] = b'+';
-                    } else {
-                        bmap[x+y_*self.width] = b'|';
-                    }
+            }
+            for y_ in cmp::min(y, p_y) ..= cmp::max(y, p_y) {
+                if bmap[x+y_*self.width] == '-' {
+                    bmap[x+y_*self.width] = '+';
+                } else {
+                    bmap[x+y_*self.width] = '|';
                 }
             }
         }
 
         // show bubbles
         for bubble in &self.bubbles {
-            let x = bubble.borrow().x as usize;
-            let y = bubble.borrow().y as usize;
-            bmap[x+y*self.width] = b'o';
+            let x = (bubble.borrow().x - self.lower_x) as usize;
+            let y = (bubble.borrow().y - self.lower_y) as usize;
+            bmap[x+y*self.width] = 'o';
         }
 
         (self.width, self.height, bmap)
     }
-}
 
+    // find each bubble's depth in the parent tree
+    fn bubble_depths(&self) -> HashMap<*const RefCell<Bubble>, usize> {
+        // parents always precede children
+        let mut depths = HashMap::new();
+        for bubble in &self.bubbles {
+            let depth = bubble.borrow().parent.as_ref()
+                .map_or(0, |parent| depths[&Rc::as_ptr(parent)] + 1);
+            depths.insert(Rc::as_ptr(bubble), depth);
+        }
+        depths
+    }
 
-// keep track of unresolved constraints
-//
-// this requires a bit of a unique data structure, we need to be
-// able to:
-// 1. insert unique constraints
-// 2. remove unique constraints
-// 3. choose a random constraint with the lowest amount of
-//    possiblities remaining
-//
-// unfortunately since we want this to be deterministic and random
-// we can't just choose an arbitrary item from a BTreeMap/HashMap
-//
-#[derive(Debug, Clone)]
-struct ConstraintSet {
-    buckets: BTreeMap<u32, (
-        HashMap<(usize, usize), usize>,
-        Vec<(usize, usize)>
-    )>
-}
+    // style each char in our bubble map by its bubble's depth in the
+    // parent tree, hallways take the depth of their deeper end
+    fn style_bubble_map(&self) -> Vec<Style> {
+        let depths = self.bubble_depths();
 
-impl ConstraintSet {
-    fn new() -> ConstraintSet {
-        ConstraintSet{buckets: BTreeMap::new()}
-    }
+        let mut styles = vec![STYLE_NONE; self.width*self.height];
+        for bubble in &self.bubbles {
+            let x = (bubble.borrow().x - self.lower_x) as usize;
+            let y = (bubble.borrow().y - self.lower_y) as usize;
+            let r = bubble.borrow().r;
+            let style = depth_style(depths[&Rc::as_ptr(bubble)]);
+            for y_ in y.saturating_sub(r) ..= cmp::min(y+r, self.height-1) {
+                for x_ in x.saturating_sub(r) ..= cmp::min(x+r, self.width-1) {
+                    if
+                        distsq(
+                            (x_ as isize, y_ as isize),
+                            (x as isize, y as isize)
+                        ) <= sq(r)
+                    {
+                        styles[x_+y_*self.width] = style;
+                    }
+                }
+            }
+        }
 
-    fn insert(&mut self, c: u32, x: usize, y: usize) -> bool {
-        let (ref mut map, ref mut bucket) = self.buckets.entry(c)
-            .or_insert_with(|| (HashMap::new(), Vec::new()));
-        match map.entry((x, y)) {
-            hash_map::Entry::Occupied(_) => false,
-            hash_map::Entry::Vacant(e) => {
-                let i = bucket.len();
-                bucket.push((x, y));
-                e.insert(i);
-                true
+        for (a, b) in &self.hallways {
+            let x = (a.borrow().x - self.lower_x) as usize;
+            let y = (a.borrow().y - self.lower_y) as usize;
+            let p_x = (b.borrow().x - self.lower_x) as usize;
+            let p_y = (b.borrow().y - self.lower_y) as usize;
+            let style = depth_style(cmp::max(
+                depths[&Rc::as_ptr(a)],
+                depths[&Rc::as_ptr(b)],
+            ));
+            for x_ in cmp::min(x, p_x) ..= cmp::max(x, p_x) {
+                styles[x_+y*self.width] = style;
+            }
+            for y_ in cmp::min(y, p_y) ..= cmp::max(y, p_y) {
+                styles[p_x+y_*self.width] = style;
             }
         }
+
+        for bubble in &self.bubbles {
+            let x = (bubble.borrow().x - self.lower_x) as usize;
+            let y = (bubble.borrow().y - self.lower_y) as usize;
+            styles[x+y*self.width] = depth_style(depths[&Rc::as_ptr(bubble)]);
+        }
+
+        styles
     }
+}
 
-    fn remove(&mut self, c: u32, x: usize, y: usize) -> bool {
-        match self.buckets.entry(c) {
-            btree_map::Entry::Occupied(mut e) => {
-                let (ref mut map, ref mut bucket) = e.get_mut();
-                match map.entry((x, y)) {
-                    // one entry? drop bucket
-                    hash_map::Entry::Occupied(_) if bucket.len() <= 1 => {
-                        e.remove();
-                        true
-                    }
-                    // more entries? need to swap-remove, this gets
-                    // a bit messy since we also need to update the
-                    // swapped entry's map entry
-                    hash_map::Entry::Occupied(e) => {
-                        let i = e.remove();
-                        if i < bucket.len()-1 {
-                            let (x_, y_) = bucket.pop().unwrap();
-                            bucket[i] = (x_, y_);
-                            map.insert((x_, y_), i);
-                        }
-                        true
-                    }
-                    hash_map::Entry::Vacant(_) => false,
-                }
-            },
-            btree_map::Entry::Vacant(_) => false,
+
+impl WaveStation {
+    // mark a cell as inside our station, what this allows depends on
+    // the cell's zone
+    fn mark_inside(&mut self, x: usize, y: usize) {
+        if self.frozen.iter().any(|&(x_, y_, r)| {
+            distsq((x as isize, y as isize), (x_, y_)) <= sq(r)
+        }) {
+            return;
         }
+
+        self.cmap[x+y*self.wwidth] = TILE_ALL & !TILE_SPACE & !TILE_PINNED
+            & self.zmap[x+y*self.wwidth].map_or(TILE_ALL, |zone| zone.tiles());
     }
 
-    fn pop(&mut self, prng: &mut Xorshift64) -> Option<(u32, usize, usize)> {
-        match self.buckets.first_key_value() {
-            Some((&c, (_, ref bucket))) => {
-                // in case of tie, choose randomly
-                let (x, y) = bucket[prng.range(0..bucket.len())];
-                self.remove(c, x, y);
-                Some((c, x, y))
+    // find the window wfc needs to work on, this is the bounding box of
+    // anything that changed, plus a margin so we can see any fixed
+    // neighbors, in tiles
+    fn dirty_window(
+        &self,
+        delta_bubbles: &[Rc<RefCell<Bubble>>],
+        delta_hallways: &[Hallway],
+        delta_regions: &[(isize, isize, usize, usize)],
+    ) -> (isize, isize, usize, usize) {
+        let scale = self.scale as isize;
+        let mut bounds: Option<(isize, isize, isize, isize)> = None;
+        let mut include = |lower_x, lower_y, upper_x, upper_y| {
+            bounds = Some(match bounds {
+                Some((lower_x_, lower_y_, upper_x_, upper_y_)) => (
+                    cmp::min(lower_x, lower_x_),
+                    cmp::min(lower_y, lower_y_),
+                    cmp::max(upper_x, upper_x_),
+                    cmp::max(upper_y, upper_y_),
+                ),
+                None => (lower_x, lower_y, upper_x, upper_y),
+            });
+        };
+
+        for bubble in delta_bubbles {
+            let x = bubble.borrow().x * scale;
+            let y = bubble.borrow().y * scale;
+            let r = (bubble.borrow().r * self.scale) as isize;
+            include(x-r, y-r, x+r, y+r);
+        }
+        for (a, b) in delta_hallways {
+            for (x, y) in self.hallway_tiles(a, b) {
+                include(x, y, x, y);
+            }
+        }
+        for &(x, y, width, height) in delta_regions {
+            if width > 0 && height > 0 {
+                include(x, y, x+width as isize-1, y+height as isize-1);
+            }
+        }
+
+        // clamp to our bounding box
+        match bounds {
+            Some((lower_x, lower_y, upper_x, upper_y)) => {
+                let lower_x = cmp::max(lower_x - WFC_MARGIN, self.cx);
+                let lower_y = cmp::max(lower_y - WFC_MARGIN, self.cy);
+                let upper_x = cmp::min(
+                    upper_x + WFC_MARGIN,
+                    self.cx + self.cwidth as isize - 1,
+                );
+                let upper_y = cmp::min(
+                    upper_y + WFC_MARGIN,
+                    self.cy + self.cheight as isize - 1,
+                );
+                (
+                    lower_x,
+                    lower_y,
+                    cmp::max(upper_x+1-lower_x, 0) as usize,
+                    cmp::max(upper_y+1-lower_y, 0) as usize,
+                )
             }
-            None => None,
+            None => (self.cx, self.cy, 0, 0),
         }
     }
-}
 
-impl WaveStation {
     // evaluate any unresolved constraints in our constraint map
     // with wave-function collapse
     fn wfc(
@@ -642,67 +1437,111 @@ impl WaveStation {
         anim_sleep: Option<Duration>,
     ) -> bool {
         let start = Instant::now();
+        let start_cycles = self.wfc_cycles;
+        let start_propagations = self.wfc_propagations;
 
         // lazily initialize our initial constraint map since wfc is
         // expensive and may not be used, by default all constraints
         // should be space
-        let (delta_bubbles, delta_hallways) = match self.cmap.len() {
-            0 =>  {
-                self.cwidth = self.width*self.scale;
-                self.cheight = self.height*self.scale;
-                self.cmap = vec![TILE_SPACE; self.cwidth*self.cheight];
-
+        let (mut delta_bubbles, delta_hallways) = match self.tiles.is_empty() {
+            true =>  {
                 // run wfc over all bubbles
-                (&self.bubbles, &self.bubbles)
+                (self.bubbles.clone(), self.hallways.clone())
             },
-            _ => {
+            false => {
                 // only run wfc on new/oudated bubbles/hallways
-                (&self.delta_bubbles, &self.delta_hallways)
+                (
+                    mem::take(&mut self.delta_bubbles),
+                    mem::take(&mut self.delta_hallways),
+                )
             }
         };
+        let delta_regions = mem::take(&mut self.delta_regions);
+
+        // frozen bubbles stay as they are
+        delta_bubbles.retain(|bubble| !bubble.borrow().frozen);
+
+        // our whole bounding box, in tiles
+        self.cx = self.lower_x * self.scale as isize;
+        self.cy = self.lower_y * self.scale as isize;
+        self.cwidth = self.width*self.scale;
+        self.cheight = self.height*self.scale;
+
+        // copy out a dense window of our constraint map to work on, this
+        // only covers what changed
+        (self.wx, self.wy, self.wwidth, self.wheight) = self.dirty_window(
+            &delta_bubbles,
+            &delta_hallways,
+            &delta_regions,
+        );
+        (self.cmap, self.zmap) = self.tiles.read(
+            self.wx, self.wy,
+            self.wwidth, self.wheight,
+        );
 
-        // mark bubbles as not space
-        for bubble in delta_bubbles {
-            let x = bubble.borrow().x as usize * self.scale;
-            let y = bubble.borrow().y as usize * self.scale;
-            let r = bubble.borrow().r * self.scale;
-            for y_ in 0..self.cheight {
-                for x_ in 0..self.cwidth {
-                    if
-                        distsq(
-                            (x_ as isize, y_ as isize),
-                            (x as isize, y as isize))
-                            <= sq(r)
-                    {
-                        self.cmap[x_+y_*self.cwidth]
-                            = TILE_ALL & !TILE_SPACE;
+        // note we only need frozen bubbles that overlap our window
+        self.frozen = self.bubbles.iter()
+            .filter(|bubble| bubble.borrow().frozen)
+            .map(|bubble| (
+                bubble.borrow().x * self.scale as isize - self.wx,
+                bubble.borrow().y * self.scale as isize - self.wy,
+                bubble.borrow().r * self.scale,
+            ))
+            .filter(|&(x, y, r)| {
+                let r = r as isize;
+                x+r >= 0 && x-r < self.wwidth as isize
+                    && y+r >= 0 && y-r < self.wheight as isize
+            })
+            .collect();
+
+        // mark bubbles as not space, note these are always inside our
+        // window
+        for bubble in &delta_bubbles {
+            let (x, y) = self.window_tile(
+                bubble.borrow().x,
+                bubble.borrow().y,
+            );
+            let r = (bubble.borrow().r * self.scale) as isize;
+            for y_ in cmp::max(y-r, 0)
+                ..= cmp::min(y+r, self.wheight as isize-1)
+            {
+                for x_ in cmp::max(x-r, 0)
+                    ..= cmp::min(x+r, self.wwidth as isize-1)
+                {
+                    if distsq((x_, y_), (x, y)) <= sq(r as usize) {
+                        let (x_, y_) = (x_ as usize, y_ as usize);
+                        self.zmap[x_+y_*self.wwidth] = bubble.borrow().zone;
+                        self.mark_inside(x_, y_);
                     }
                 }
             }
         }
 
         // mark hallway walls as not space
-        for bubble in delta_hallways {
-            if let Some(parent) = &bubble.borrow().parent {
-                let a_x = bubble.borrow().x as usize * self.scale;
-                let a_y = bubble.borrow().y as usize * self.scale;
-                let b_x = parent.borrow().x as usize * self.scale;
-                let b_y = parent.borrow().y as usize * self.scale;
+        for (a, b) in &delta_hallways {
+            for (x, y) in self.hallway_tiles(a, b) {
+                self.mark_inside(
+                    (x - self.wx) as usize,
+                    (y - self.wy) as usize,
+                );
+            }
+        }
 
-                for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
-                    for r in 0..(self.scale+1)/2 {
-                        self.cmap[a_x_+(a_y+r)*self.cwidth]
-                            = TILE_ALL & !TILE_SPACE;
-                        self.cmap[a_x_+(a_y-r)*self.cwidth]
-                            = TILE_ALL & !TILE_SPACE;
-                    }
-                }
-                for a_y in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
-                    for r in 0..(self.scale+1)/2 {
-                        self.cmap[(a_x+r)+a_y*self.cwidth]
-                            = TILE_ALL & !TILE_SPACE;
-                        self.cmap[(a_x-r)+a_y*self.cwidth]
-                            = TILE_ALL & !TILE_SPACE;
+        // mark any rerolled regions as not space, as long as they're
+        // already inside our station
+        for (x, y, width, height) in delta_regions {
+            let upper_x = x + width as isize;
+            let upper_y = y + height as isize;
+            for y_ in cmp::max(y, self.wy)
+                .. cmp::min(upper_y, self.wy + self.wheight as isize)
+            {
+                for x_ in cmp::max(x, self.wx)
+                    .. cmp::min(upper_x, self.wx + self.wwidth as isize)
+                {
+                    let x_ = (x_ - self.wx) as usize;
+                    let y_ = (y_ - self.wy) as usize;
+                    if self.cmap[x_+y_*self.wwidth] != TILE_SPACE {
+                        self.mark_inside(x_, y_);
                     }
                 }
             }
@@ -710,29 +1549,61 @@ impl WaveStation {
 
         // but hallways themselves as required floor
         //
-        // note we just consider all of the hallways here! this fixes issues
-        // with hallway<->hallway intersection without needing another
-        // collision detection algorithm
+        // note we just consider all of the hallways in our window here!
+        // this fixes issues with hallway<->hallway intersection without
+        // needing another collision detection algorithm
         //
         // these should allways be floors anyways
-        for bubble in &self.bubbles {
-            if let Some(parent) = &bubble.borrow().parent {
-                let a_x = bubble.borrow().x as usize * self.scale;
-                let a_y = bubble.borrow().y as usize * self.scale;
-                let b_x = parent.borrow().x as usize * self.scale;
-                let b_y = parent.borrow().y as usize * self.scale;
+        let in_window = |x: isize, y: isize| {
+            x >= 0 && x < self.wwidth as isize
+                && y >= 0 && y < self.wheight as isize
+        };
+        let mut floors = vec![];
+        for (a, b) in &self.hallways {
+            let (a_x, a_y) = self.window_tile(a.borrow().x, a.borrow().y);
+            let (b_x, b_y) = self.window_tile(b.borrow().x, b.borrow().y);
+
+            // skip hallways that miss our window entirely, and only walk
+            // the parts that are in it
+            let lower_x = cmp::max(cmp::min(a_x, b_x), 0);
+            let upper_x = cmp::min(cmp::max(a_x, b_x), self.wwidth as isize-1);
+            let lower_y = cmp::max(cmp::min(a_y, b_y), 0);
+            let upper_y = cmp::min(cmp::max(a_y, b_y), self.wheight as isize-1);
+            if lower_x > upper_x || lower_y > upper_y {
+                continue;
+            }
 
-                for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
-                    self.cmap[a_x_+a_y*self.cwidth] = TILE_FLOOR;
-                    self.cmap[a_x_+a_y*self.cwidth] = TILE_FLOOR;
+            if in_window(lower_x, a_y) {
+                for a_x_ in lower_x ..= upper_x {
+                    floors.push((a_x_, a_y));
                 }
-                for a_y in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
-                    self.cmap[a_x+a_y*self.cwidth] = TILE_FLOOR;
-                    self.cmap[a_x+a_y*self.cwidth] = TILE_FLOOR;
+            }
+            if in_window(a_x, lower_y) {
+                for a_y in lower_y ..= upper_y {
+                    floors.push((a_x, a_y));
                 }
             }
         }
 
+        // and finally any explicitly pinned tiles
+        let mut pins = vec![];
+        for (bubble, x, y, tile) in &self.pins {
+            let (x_, y_) = self.window_tile(
+                bubble.borrow().x,
+                bubble.borrow().y,
+            );
+            if in_window(x_+x, y_+y) {
+                pins.push((x_+x, y_+y, *tile));
+            }
+        }
+
+        for (x, y) in floors {
+            self.cmap[x as usize+y as usize*self.wwidth] = TILE_FLOOR;
+        }
+        for (x, y, tile) in pins {
+            self.cmap[x as usize+y as usize*self.wwidth] = tile;
+        }
+
         // reset our deltas, these bubbles are now at least represented
         // in our constraint map
         self.delta_bubbles.clear();
@@ -745,7 +1616,7 @@ impl WaveStation {
             for y in 0..theight {
                 for x in 0..twidth {
                     write!(term, "{}",
-                        char::from_u32(tmap[x+y*twidth] as u32).unwrap()
+                        tmap[x+y*twidth]
                     ).unwrap();
                 }
                 writeln!(term).unwrap();
@@ -758,191 +1629,340 @@ impl WaveStation {
         }
 
 
-        // copy our constraint map for the core wfc algorithm, this allows
-        // us to quickly revert failed attempts
-        let init_cmap = self.cmap.clone();
-
         // figure out what we actually need to resolve
-        let mut init_unresolved: Vec<(usize, usize)> = vec![];
-        for y in 0..self.cheight {
-            for x in 0..self.cwidth {
-                let c = self.cmap[x+y*self.cwidth];
-                if c.count_ones() > 1 {
-                    init_unresolved.push((x, y));
-                }
-            }
-        }
-        let init_unresolved = init_unresolved;
+        let unresolved = self.cmap.iter()
+            .filter(|c| c.count_ones() > 1)
+            .count();
+
+        // solve our window, either all at once or in independent regions,
+        // note we move our maps out so we can still render while solving
+        let mut cmap = mem::take(&mut self.cmap);
+        let zmap = mem::take(&mut self.zmap);
+        let mut prng = self.prng.clone();
+        let mut stats = WfcStats::default();
+        let bubbles = self.bubbles.iter()
+            .filter(|bubble| !bubble.borrow().frozen)
+            .map(|bubble| {
+                let (x, y) = self.window_tile(
+                    bubble.borrow().x,
+                    bubble.borrow().y,
+                );
+                (x, y, bubble.borrow().r * self.scale)
+            })
+            // only bubbles that overlap our window
+            .filter(|&(x, y, r)| {
+                let r = r as isize;
+                x+r >= 0 && x-r < self.wwidth as isize
+                    && y+r >= 0 && y-r < self.wheight as isize
+            })
+            .collect::<Vec<_>>();
 
-        let mut success = false;
-        self.wfc_attempts = 0;
+        // animate wfc?
+        let self_ = &*self;
+        let mut anim = anim_term.map(|term| move |cmap: &[u128]| {
+            let (twidth, theight, tmap) = self_.render_tiles(
+                &self_.overlay_window(cmap)
+            );
 
-        'wfc: for _ in 0..self.attempts {
-            self.wfc_attempts += 1;
+            for y in 0..theight {
+                for x in 0..twidth {
+                    write!(term, "{}",
+                        tmap[x+y*twidth]
+                    ).unwrap();
+                }
+                writeln!(term).unwrap();
+            }
 
-            // reset to initial constraint map
-            self.cmap = init_cmap.clone();
+            term.swap();
+            if let Some(sleep) = anim_sleep {
+                thread::sleep(sleep);
+            }
+        });
+        let anim = anim.as_mut()
+            .map(|anim| anim as &mut dyn FnMut(&[u128]));
+
+        // our propagation budget covers all of our passes
+        let budget = Budget{
+            propagations: self.wfc_budget.propagations.map(|limit| {
+                limit.saturating_sub(self.wfc_propagations)
+            }),
+            ..self.wfc_budget.clone()
+        };
 
-            // keep track of all unresolved constraints
-            let mut unresolved: ConstraintSet = ConstraintSet::new();
+        let wfc = Wfc{
+            width: self.wwidth,
+            height: self.wheight,
+            zmap: &zmap,
+            live: None,
+            attempts: self.attempts,
+            budget: &budget,
+            repair: self.wfc_repair,
+            propagator: self.wfc_propagator,
+        };
+        let mut success = match (&self.overlap, self.wfc_threads) {
+            // fill interiors with patterns learned from examples? note our
+            // opts don't allow this with threads
+            (Some(overlap), _) => overlap.solve(
+                &wfc,
+                &mut cmap,
+                &mut prng,
+                &mut stats,
+                anim,
+            ),
+            (None, Some(threads)) => wfc.solve_regions(
+                &mut cmap,
+                &bubbles,
+                threads,
+                &mut prng,
+                &mut stats,
+                anim,
+            ),
+            (None, None) => wfc.solve(&mut cmap, &mut prng, &mut stats, anim),
+        };
 
-            // add all unresolved to our propagating set, these will be moved
-            // into the unresolved tree after constraints are evaluated
-            let mut propagating: Vec<(usize, usize)> = init_unresolved.clone();
+        // out of budget? fill in anything unresolved with our fallback
+        let (mut fallback, mut breaches) = (0, 0);
+        if stats.exhausted && self.wfc_fallback != Fallback::Error {
+            (fallback, breaches) = self.wfc_fallback.fill(
+                &mut cmap,
+                self.wwidth,
+                self.wheight,
+            );
+            success = true;
+        }
 
-            // core wfc algorithm
-            loop {
-                self.wfc_cycles += 1;
+        self.cmap = cmap;
+        self.zmap = zmap;
+        self.prng = prng;
+        self.wfc_attempts = stats.attempts;
+        self.wfc_cycles += stats.cycles;
+        self.wfc_propagations += stats.propagations;
+        self.wfc_repairs += stats.repairs;
+        self.wfc_exhausted |= stats.exhausted;
+        self.wfc_fallbacks += fallback;
+        self.wfc_breaches += breaches;
+
+        // write our window back into our constraint map
+        self.tiles.write(
+            self.wx, self.wy,
+            self.wwidth, self.wheight,
+            &self.cmap, &self.zmap,
+        );
+        self.cmap = vec![];
+        self.zmap = vec![];
 
-                // propagate new constraints
-                while let Some((x, y)) = propagating.pop() {
-                    self.wfc_propagations += 1;
-                    let mut c = self.cmap[x+y*self.cwidth];
+        let stop = Instant::now();
+        self.wfc_time += stop.duration_since(start);
+        self.wfc_chunks.push(WfcChunk{
+            width: self.wwidth,
+            height: self.wheight,
+            unresolved,
+            exhausted: stats.exhausted,
+            fallback,
+            breaches,
+            attempts: self.wfc_attempts,
+            cycles: self.wfc_cycles - start_cycles,
+            propagations: self.wfc_propagations - start_propagations,
+            repairs: stats.repairs,
+            time: stop.duration_since(start),
+            success,
+        });
 
-                    // for each neighbor
-                    let mut constrain = |x_: usize, y_: usize, dir: Dir| {
-                        let c_ = self.cmap[x_+y_*self.cwidth];
+        success
+    }
 
-                        // what does our neighbor allow us to be?
-                        let mut mask = 0;
-                        for i in 0..TILES.len() {
-                            if c_ & (1 << i) != 0 {
-                                mask |= TILES[i].constraints.dir(dir.flip());
-                            }
-                        }
-                        c &= mask;
-
-                        // does any of our possibilities contradict our
-                        // neighbor?
-                        for i in 0..TILES.len() {
-                            if
-                                c & (1 << i) != 0
-                                    && TILES[i].constraints.dir(dir) & c_ == 0
-                            {
-                                c &= !(1 << i);
-                            }
-                        }
-                    };
-                    
-                    if x > 0 { constrain(x-1, y, Dir::W); }
-                    if y > 0 { constrain(x, y-1, Dir::N); }
-                    if x < self.cwidth-1 { constrain(x+1, y, Dir::E); }
-                    if y < self.cheight-1 { constrain(x, y+1, Dir::S); }
-
-                    // did we actually change anything?
-                    if self.cmap[x+y*self.cwidth] != c {
-                        // update our map
-                        let count = self.cmap[x+y*self.cwidth].count_ones();
-                        let count_ = c.count_ones();
-                        self.cmap[x+y*self.cwidth] = c;
-                        // contradiction? abort the current wfc
-                        if c == 0 {
-                            continue 'wfc;
-                        }
-                        // move into different bucket
-                        unresolved.remove(count, x, y);
-                        unresolved.insert(count_, x, y);
-                        // propagate constraints to our neighbors
-                        if x > 0 { propagating.push((x-1, y)); }
-                        if y > 0 { propagating.push((x, y-1)); }
-                        if x < self.cwidth-1 { propagating.push((x+1, y)); }
-                        if y < self.cheight-1 { propagating.push((x, y+1)); }
-                    }
+    // find the tiles a bubble covers
+    fn bubble_tiles(
+        &self,
+        bubble: &Rc<RefCell<Bubble>>,
+    ) -> Vec<(isize, isize)> {
+        let x = bubble.borrow().x * self.scale as isize;
+        let y = bubble.borrow().y * self.scale as isize;
+        let r = bubble.borrow().r * self.scale;
+
+        let mut tiles = vec![];
+        for y_ in y-r as isize ..= y+r as isize {
+            for x_ in x-r as isize ..= x+r as isize {
+                if distsq((x_, y_), (x, y)) <= sq(r) {
+                    tiles.push((x_, y_));
                 }
+            }
+        }
+        tiles
+    }
 
-                // animate wfc?
-                if let Some(term) = anim_term.as_mut() {
-                    let (twidth, theight, tmap) = self.render_tile_map();
-
-                    for y in 0..theight {
-                        for x in 0..twidth {
-                            write!(term, "{}",
-                                char::from_u32(tmap[x+y*twidth] as u32).unwrap()
-                            ).unwrap();
-                        }
-                        writeln!(term).unwrap();
-                    }
+    // find the tiles a hallway covers, including its walls
+    fn hallway_tiles(
+        &self,
+        a: &Rc<RefCell<Bubble>>,
+        b: &Rc<RefCell<Bubble>>,
+    ) -> Vec<(isize, isize)> {
+        let scale = self.scale as isize;
+        let a_x = a.borrow().x * scale;
+        let a_y = a.borrow().y * scale;
+        let b_x = b.borrow().x * scale;
+        let b_y = b.borrow().y * scale;
+
+        let mut tiles = vec![];
+        for a_x_ in cmp::min(a_x, b_x) ..= cmp::max(a_x, b_x) {
+            for r in 0..(scale+1)/2 {
+                tiles.push((a_x_, a_y+r));
+                tiles.push((a_x_, a_y-r));
+            }
+        }
+        for a_y in cmp::min(a_y, b_y) ..= cmp::max(a_y, b_y) {
+            for r in 0..(scale+1)/2 {
+                tiles.push((a_x+r, a_y));
+                tiles.push((a_x-r, a_y));
+            }
+        }
+        tiles
+    }
 
-                    term.swap();
-                    if let Some(sleep) = anim_sleep {
-                        thread::sleep(sleep);
-                    }
-                }
+    // convert cell coordinates into tile coordinates in our wfc window,
+    // note these may be outside of the window
+    fn window_tile(&self, x: isize, y: isize) -> (isize, isize) {
+        (
+            x*self.scale as isize - self.wx,
+            y*self.scale as isize - self.wy,
+        )
+    }
 
-                // do we have unresolved constraints? choose the most-resolved
-                while propagating.len() == 0 {
-                    match unresolved.pop(&mut self.prng) {
-                        Some((_, x, y)) => {
-                            // randomly assign it to one of its options
-                            let mut c = self.cmap[x+y*self.cwidth];
-                            debug_assert!(c.count_ones() > 0);
-                            if c.count_ones() > 1 {
-                                let choice = self.prng.range(
-                                    0..c.count_ones() as usize
-                                );
-                                // figure out which bit this actually is, kinda
-                                // complicated
-                                for _ in 0..choice {
-                                    c &= !(1 << (128-1-c.leading_zeros()));
-                                }
-                                c &= !((1 << (128-1-c.leading_zeros()))-1);
-
-                                // update our map
-                                self.cmap[x+y*self.cwidth] = c;
-                                // propagate constraints to our neighbors
-                                if x > 0 { propagating.push((x-1, y)); }
-                                if y > 0 { propagating.push((x, y-1)); }
-                                if x < self.cwidth-1 {
-                                    propagating.push((x+1, y));
-                                }
-                                if y < self.cheight-1 {
-                                    propagating.push((x, y+1));
-                                }
-                            }
-                        }
-                        None => {
-                            success = true;
-                            break 'wfc;
-                        }
-                    }
-                }
-            }
+    // copy out our whole bounding box from our constraint map, with a
+    // wfc window in progress on top
+    fn overlay_window<'a>(&self, cmap: &'a [u128]) -> Cow<'a, [u128]> {
+        if
+            self.wx == self.cx && self.wy == self.cy
+                && self.wwidth == self.cwidth
+                && self.wheight == self.cheight
+        {
+            return Cow::Borrowed(cmap);
         }
 
-        let stop = Instant::now();
-        self.wfc_time += stop.duration_since(start);
+        let (mut cmap_, _) = self.tiles.read(
+            self.cx, self.cy,
+            self.cwidth, self.cheight,
+        );
+        let x = (self.wx - self.cx) as usize;
+        let y = (self.wy - self.cy) as usize;
+        for y_ in 0..self.wheight {
+            cmap_[x+(y+y_)*self.cwidth .. x+self.wwidth+(y+y_)*self.cwidth]
+                .copy_from_slice(
+                    &cmap[y_*self.wwidth .. (y_+1)*self.wwidth]
+                );
+        }
+        Cow::Owned(cmap_)
+    }
 
-        success
+    // render our wfc window if we're in the middle of wfc, otherwise
+    // copy out the same window from our constraint map
+    fn render_window(&self) -> Cow<'_, [u128]> {
+        if !self.cmap.is_empty() {
+            self.overlay_window(&self.cmap)
+        } else {
+            Cow::Owned(self.tiles.read(
+                self.cx, self.cy,
+                self.cwidth, self.cheight,
+            ).0)
+        }
     }
 
     // render tile map
-    fn render_tile_map(&self) -> (usize, usize, Vec<u8>) {
+    fn render_tile_map(&self) -> (usize, usize, Vec<char>) {
+        self.render_tiles(&self.render_window())
+    }
+
+    // render a constraint map the size of our wfc window
+    fn render_tiles(&self, cmap: &[u128]) -> (usize, usize, Vec<char>) {
         // convert our constraint map into a tile map
-        let mut tmap = vec![b'?'; self.cwidth*self.cheight*2];
+        let mut tmap = vec!['?'; self.cwidth*self.cheight*2];
         for y in 0..self.cheight {
             for x in 0..self.cwidth { 
-                let ascii = match self.cmap[x+y*self.cwidth] {
-                    0 => b"!!",
+                let glyphs = match cmap[x+y*self.cwidth] {
+                    0 => ['!', '!'],
                     x if x.count_ones() == 1 => {
-                        TILES[128-1-x.leading_zeros() as usize].ascii
+                        TILES[128-1-x.leading_zeros() as usize]
+                            .glyphs(self.charset)
                     },
-                    x if x.count_ones() == 2 => b"22",
-                    x if x.count_ones() == 3 => b"33",
-                    x if x.count_ones() == 4 => b"44",
-                    x if x.count_ones() == 5 => b"55",
-                    x if x.count_ones() == 6 => b"66",
-                    x if x.count_ones() == 7 => b"77",
-                    x if x.count_ones() == 8 => b"88",
-                    x if x.count_ones() == 9 => b"99",
-                    _ => b"??",
+                    x if x.count_ones() == 2 => ['2', '2'],
+                    x if x.count_ones() == 3 => ['3', '3'],
+                    x if x.count_ones() == 4 => ['4', '4'],
+                    x if x.count_ones() == 5 => ['5', '5'],
+                    x if x.count_ones() == 6 => ['6', '6'],
+                    x if x.count_ones() == 7 => ['7', '7'],
+                    x if x.count_ones() == 8 => ['8', '8'],
+                    x if x.count_ones() == 9 => ['9', '9'],
+                    _ => ['?', '?'],
                 };
                 tmap[(x+y*self.cwidth)*2 .. (x+y*self.cwidth)*2+2]
-                    .copy_from_slice(ascii);
+                    .copy_from_slice(&glyphs);
+            }
+        }
+
+        // box-drawing characters don't know about their neighbors, so
+        // join any lines that run into each other, this turns inner walls
+        // meeting outer walls into tees
+        if self.charset == Charset::Unicode {
+            let width = self.cwidth*2;
+            let arms = |x: usize, y: usize| box_arms(tmap[x+y*width]);
+            let mut joined = tmap.clone();
+            for y in 0..self.cheight {
+                for x in 0..width {
+                    let mut arms_ = match arms(x, y) {
+                        Some(arms_) => arms_,
+                        None => continue,
+                    };
+                    let arms__ = arms_;
+                    if y > 0 && arms(x, y-1).is_some_and(|a| a[2]) {
+                        arms_[0] = true;
+                    }
+                    if x+1 < width && arms(x+1, y).is_some_and(|a| a[3]) {
+                        arms_[1] = true;
+                    }
+                    if
+                        y+1 < self.cheight
+                            && arms(x, y+1).is_some_and(|a| a[0])
+                    {
+                        arms_[2] = true;
+                    }
+                    if x > 0 && arms(x-1, y).is_some_and(|a| a[1]) {
+                        arms_[3] = true;
+                    }
+                    if arms_ != arms__ {
+                        joined[x+y*width] = box_char(arms_).unwrap();
+                    }
+                }
             }
+            tmap = joined;
         }
 
         (self.cwidth*2, self.cheight, tmap)
     }
+
+    // style each char in our tile map by tile class
+    fn style_tile_map(&self) -> Vec<Style> {
+        let cmap = self.render_window();
+
+        // floors inside bubbles are rooms, anything else is a hallway
+        let rooms = self.bubbles.iter()
+            .flat_map(|bubble| self.bubble_tiles(bubble))
+            .collect::<HashSet<_>>();
+
+        let mut styles = vec![STYLE_NONE; self.cwidth*self.cheight*2];
+        for y in 0..self.cheight {
+            for x in 0..self.cwidth {
+                let in_room = rooms.contains(&(
+                    x as isize + self.cx,
+                    y as isize + self.cy,
+                ));
+                let style = tile_style(cmap[x+y*self.cwidth], in_room);
+                styles[(x+y*self.cwidth)*2] = style;
+                styles[(x+y*self.cwidth)*2+1] = style;
+            }
+        }
+
+        styles
+    }
 }
 
 // serialization
@@ -955,7 +1975,10 @@ impl Serialize for WaveStation {
     ) -> Result<S::Ok, S::Error> {
         // serialize bubbles, note parent always precedes the current
         // bubble, this may help deserialization
-        struct SerializeBubbles<'a>(&'a [Rc<RefCell<Bubble>>]);
+        //
+        // bubbles are translated so the bounding box starts at 0,0
+        //
+        struct SerializeBubbles<'a>(&'a [Rc<RefCell<Bubble>>], (isize, isize));
 
         impl Serialize for SerializeBubbles<'_> {
             fn serialize<S: serde::Serializer>(
@@ -968,6 +1991,8 @@ impl Serialize for WaveStation {
                     y: usize,
                     r: usize,
                     parent: Option<usize>,
+                    #[serde(skip_serializing_if="Option::is_none")]
+                    zone: Option<Zone>,
                 }
 
                 // keep track of parents we've seen
@@ -976,24 +2001,53 @@ impl Serialize for WaveStation {
 
                 let mut s = serializer.serialize_seq(Some(self.0.len()))?;
                 for (i, bubble) in self.0.iter().enumerate() {
-                    parent_map.insert(Rc::as_ptr(&bubble), i);
+                    parent_map.insert(Rc::as_ptr(bubble), i);
 
                     // find parent index
                     let parent = bubble.borrow().parent.as_ref().map(|parent| {
-                        *parent_map.get(&Rc::as_ptr(&parent)).unwrap()
+                        *parent_map.get(&Rc::as_ptr(parent)).unwrap()
                     });
 
                     s.serialize_element(&SerializeBubble{
-                        x: bubble.borrow().x as usize,
-                        y: bubble.borrow().y as usize,
+                        x: (bubble.borrow().x - self.1.0) as usize,
+                        y: (bubble.borrow().y - self.1.1) as usize,
                         r: bubble.borrow().r,
-                        parent: parent,
+                        parent,
+                        zone: bubble.borrow().zone,
                     })?;
                 }
                 s.end()
             }
         }
 
+        // serialize hallways as pairs of bubble indices
+        struct SerializeHallways<'a>(
+            &'a [Rc<RefCell<Bubble>>],
+            &'a [Hallway],
+        );
+
+        impl Serialize for SerializeHallways<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S
+            ) -> Result<S::Ok, S::Error> {
+                let bubble_map: HashMap<*const RefCell<Bubble>, usize>
+                    = self.0.iter()
+                        .enumerate()
+                        .map(|(i, bubble)| (Rc::as_ptr(bubble), i))
+                        .collect();
+
+                let mut s = serializer.serialize_seq(Some(self.1.len()))?;
+                for (a, b) in self.1 {
+                    s.serialize_element(&(
+                        *bubble_map.get(&Rc::as_ptr(a)).unwrap(),
+                        *bubble_map.get(&Rc::as_ptr(b)).unwrap(),
+                    ))?;
+                }
+                s.end()
+            }
+        }
+
         // serialize constraints to tiles
         struct SerializeConstraints<'a>(&'a [u128]);
 
@@ -1021,19 +2075,32 @@ impl Serialize for WaveStation {
         struct SerializeWaveState<'a> {
             seed: u64,
             prng: u64,
+            // where the root bubble ends up after translation
+            origin: (usize, usize),
             bubbles: SerializeBubbles<'a>,
+            hallways: SerializeHallways<'a>,
             tiles: Option<SerializeTiles<'a>>,
         }
 
+        let cmap = self.tiles.read(
+            self.cx, self.cy,
+            self.cwidth, self.cheight,
+        ).0;
+
         SerializeWaveState{
             seed: self.seed,
             prng: self.prng.0,
-            bubbles: SerializeBubbles(&self.bubbles),
-            tiles: if self.cmap.len() > 0 {
+            origin: (-self.lower_x as usize, -self.lower_y as usize),
+            bubbles: SerializeBubbles(
+                &self.bubbles,
+                (self.lower_x, self.lower_y),
+            ),
+            hallways: SerializeHallways(&self.bubbles, &self.hallways),
+            tiles: if !self.tiles.is_empty() {
                 Some(SerializeTiles{
                     width: self.cwidth,
                     height: self.cheight,
-                    tiles: SerializeConstraints(&self.cmap),
+                    tiles: SerializeConstraints(&cmap),
                 })
             } else {
                 None
@@ -1044,29 +2111,51 @@ impl Serialize for WaveStation {
 
 
 fn parse_u64(s: &str) -> Result<u64, num::ParseIntError> {
-    if s.starts_with("0x") {
-        Ok(u64::from_str_radix(&s[2..], 16)?)
-    } else if s.starts_with("0o") {
-        Ok(u64::from_str_radix(&s[2..], 8)?)
-    } else if s.starts_with("0b") {
-        Ok(u64::from_str_radix(&s[2..], 2)?)
+    if let Some(s) = s.strip_prefix("0x") {
+        Ok(u64::from_str_radix(s, 16)?)
+    } else if let Some(s) = s.strip_prefix("0o") {
+        Ok(u64::from_str_radix(s, 8)?)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        Ok(u64::from_str_radix(s, 2)?)
     } else {
         Ok(u64::from_str(s)?)
     }
 }
 
 fn parse_usize(s: &str) -> Result<usize, num::ParseIntError> {
-    if s.starts_with("0x") {
-        Ok(usize::from_str_radix(&s[2..], 16)?)
-    } else if s.starts_with("0o") {
-        Ok(usize::from_str_radix(&s[2..], 8)?)
-    } else if s.starts_with("0b") {
-        Ok(usize::from_str_radix(&s[2..], 2)?)
+    if let Some(s) = s.strip_prefix("0x") {
+        Ok(usize::from_str_radix(s, 16)?)
+    } else if let Some(s) = s.strip_prefix("0o") {
+        Ok(usize::from_str_radix(s, 8)?)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        Ok(usize::from_str_radix(s, 2)?)
     } else {
         Ok(usize::from_str(s)?)
     }
 }
 
+fn parse_point(s: &str) -> Result<(isize, isize), String> {
+    let (x, y) = s.split_once(',')
+        .ok_or_else(|| format!("expected x,y, found {:?}", s))?;
+    Ok((
+        isize::from_str(x.trim()).map_err(|e| e.to_string())?,
+        isize::from_str(y.trim()).map_err(|e| e.to_string())?,
+    ))
+}
+
+fn parse_region(s: &str) -> Result<(isize, isize, usize, usize), String> {
+    let parts = s.split(',').map(|p| p.trim()).collect::<Vec<_>>();
+    if parts.len() != 4 {
+        return Err(format!("expected x,y,width,height, found {:?}", s));
+    }
+    Ok((
+        isize::from_str(parts[0]).map_err(|e| e.to_string())?,
+        isize::from_str(parts[1]).map_err(|e| e.to_string())?,
+        parse_usize(parts[2]).map_err(|e| e.to_string())?,
+        parse_usize(parts[3]).map_err(|e| e.to_string())?,
+    ))
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all="kebab")]
 struct Opt {
@@ -1093,6 +2182,101 @@ struct Opt {
     #[structopt(long, default_value="1", parse(try_from_str=parse_usize))]
     clearance: usize,
 
+    /// Ratio of extra hallways to add between nearby bubbles, creating
+    /// loops.
+    #[structopt(long, default_value="0")]
+    loops: f64,
+
+    /// Optional mask the station must fit inside, either a png or ascii
+    /// art.
+    ///
+    /// In ascii art any non-whitespace character is inside the mask, and
+    /// an 'o' marks where to start generating. Otherwise generation starts
+    /// near the center of the mask.
+    #[structopt(long)]
+    mask: Option<PathBuf>,
+
+    /// Number of cells for each character/pixel in the mask.
+    #[structopt(long, default_value="1", parse(try_from_str=parse_usize))]
+    mask_scale: usize,
+
+    /// Example station maps to learn patterns from, in the same style as
+    /// --tile-map.
+    ///
+    /// Bubble and hallway interiors are then filled with these patterns,
+    /// an overlapping model, instead of just our tile constraints. This
+    /// can't be combined with --wfc-threads.
+    #[structopt(long, number_of_values=1, conflicts_with="wfc-threads")]
+    overlap: Vec<PathBuf>,
+
+    /// Size of the patterns we learn from --overlap examples, in tiles.
+    #[structopt(long, default_value="3", parse(try_from_str=parse_usize))]
+    overlap_n: usize,
+
+    /// Assign zones to bubbles, either "depth" or "cluster".
+    ///
+    /// Zones change how rooms are laid out: habitation has lots of small
+    /// rooms, engineering has lots of doors, hydroponics has long open
+    /// rooms, and cargo has no inner walls at all.
+    #[structopt(long)]
+    zones: Option<ZoneMode>,
+
+    /// Number of bubbles deep each zone is when assigning zones by depth.
+    #[structopt(long, default_value="3", parse(try_from_str=parse_usize))]
+    zone_depth: usize,
+
+    /// Probability a bubble starts a new zone when assigning zones by
+    /// cluster.
+    #[structopt(long, default_value="0.25")]
+    zone_p: f64,
+
+    /// Number of decks to stack on top of each other, connected by lifts.
+    #[structopt(long, default_value="1", parse(try_from_str=parse_usize))]
+    decks: usize,
+
+    /// Keep growing toward a point, x,y in tiles relative to the root
+    /// bubble, after reaching the requested size.
+    ///
+    /// This can be provided multiple times to follow a path, for example
+    /// where a player walks, starting at the root bubble. Tiles near each
+    /// point are frozen before growing toward the next. Negative points
+    /// need an equals sign, --toward=-10,20.
+    #[structopt(long, parse(try_from_str=parse_point), number_of_values=1)]
+    toward: Vec<(isize, isize)>,
+
+    /// Distance in tiles around each point that gets frozen.
+    #[structopt(long, default_value="16", parse(try_from_str=parse_usize))]
+    view: usize,
+
+    /// Remove a bubble, by index, along with its subtree after
+    /// generating.
+    #[structopt(long, parse(try_from_str=parse_usize), number_of_values=1)]
+    remove: Vec<usize>,
+
+    /// Remove a hallway, by index, after generating.
+    ///
+    /// Removing a hallway that isn't a loop also removes the subtree it
+    /// leads to.
+    #[structopt(long, parse(try_from_str=parse_usize), number_of_values=1)]
+    remove_hallway: Vec<usize>,
+
+    /// Re-roll the tiles in a bubble, by index, after generating.
+    #[structopt(long, parse(try_from_str=parse_usize), number_of_values=1)]
+    reroll: Vec<usize>,
+
+    /// Re-roll the tiles in a region after generating, x,y,width,height in
+    /// tiles relative to the root bubble.
+    #[structopt(long, parse(try_from_str=parse_region), number_of_values=1)]
+    reroll_region: Vec<(isize, isize, usize, usize)>,
+
+    /// Deck to remove/re-roll things in.
+    #[structopt(long, default_value="0", parse(try_from_str=parse_usize))]
+    edit_deck: usize,
+
+    /// Explore the station in an interactive viewer after generating.
+    #[structopt(short, long)]
+    interactive: bool,
+
     /// Show a small map.
     #[structopt(short, long, visible_alias="small")]
     small_map: bool,
@@ -1123,6 +2307,64 @@ struct Opt {
     #[structopt(long, default_value="1000", parse(try_from_str=parse_u64))]
     attempts: u64,
 
+    /// Solve the tile map in independent regions, one per bubble, with
+    /// this many threads.
+    ///
+    /// Results only depend on the seed, not on the number of threads, but
+    /// differ from the default single-threaded solve.
+    #[structopt(long, parse(try_from_str=parse_usize))]
+    wfc_threads: Option<usize>,
+
+    /// Stop wave-function collapse after this many seconds, across the
+    /// whole generation.
+    #[structopt(long)]
+    wfc_deadline: Option<f64>,
+
+    /// Stop wave-function collapse after this many propagations, across
+    /// the whole generation.
+    #[structopt(long, parse(try_from_str=parse_u64))]
+    wfc_propagations: Option<u64>,
+
+    /// What to do with unresolved tiles if wave-function collapse runs out
+    /// of time or propagations, one of error, majority, or seal.
+    ///
+    /// Majority resolves tiles to floor or wall, whichever most of their
+    /// neighbors are, and seal turns them into space, with walls wherever
+    /// space would touch resolved floor.
+    #[structopt(long, default_value="error")]
+    wfc_fallback: Fallback,
+
+    /// Repair contradictions by resetting and re-solving the tiles around
+    /// them, up to this many times per attempt, before restarting.
+    ///
+    /// The neighborhood we reset grows by a tile with each repair.
+    #[structopt(long, default_value="0", parse(try_from_str=parse_usize))]
+    wfc_repair: usize,
+
+    /// How to propagate constraints in wave-function collapse, one of naive
+    /// or ac4.
+    ///
+    /// Ac4 keeps support counts for each tile, only removing tiles once
+    /// nothing supports them.
+    #[structopt(long, default_value="naive")]
+    propagator: Propagator,
+
+    /// Target number of rooms, and optionally doors, for bubbles of a
+    /// given radius, radius:rooms[:doors].
+    ///
+    /// Rooms can be a number, a range min-max, or min- for no max. Each
+    /// bubble uses the target with the largest radius, in cells, that
+    /// fits it. Bubbles that miss their target are re-solved. This can be
+    /// provided multiple times, for example --bubble-target 1:1
+    /// --bubble-target 3:8-.
+    #[structopt(long, number_of_values=1)]
+    bubble_target: Vec<BubbleTarget>,
+
+    /// Number of times to re-solve bubbles that miss their
+    /// --bubble-target before giving up.
+    #[structopt(long, default_value="16", parse(try_from_str=parse_usize))]
+    target_rerolls: usize,
+
     /// How much station size to generate at once.
     ///
     /// Larger values may increase performance, but at a risk of increasing
@@ -1158,79 +2400,182 @@ struct Opt {
     #[structopt(long)]
     tile_sleep: Option<f64>,
 
+    /// Characters to render tiles with, either ascii or unicode.
+    #[structopt(long, default_value="ascii")]
+    charset: Charset,
+
+    /// Color maps, one of auto, always, or never.
+    #[structopt(long, default_value="auto")]
+    color: ColorWhen,
+
+    /// Record any animations to an asciinema cast file.
+    #[structopt(long)]
+    record: Option<PathBuf>,
+
+    /// Record any animations to an animated gif.
+    #[structopt(long)]
+    gif: Option<PathBuf>,
+
+    /// Delay between gif frames in seconds.
+    #[structopt(long, default_value="0.1")]
+    gif_delay: f64,
+
+    /// Only keep every n'th frame in the gif.
+    #[structopt(long, default_value="1", parse(try_from_str=parse_usize))]
+    gif_skip: usize,
+
     /// Output generation station in json.
     #[structopt(short, long)]
     output: Option<PathBuf>,
 
+    /// Output generation stats in json.
+    #[structopt(long)]
+    stats_json: Option<PathBuf>,
+
     /// Only generate and output bubbles if outputing json.
     #[structopt(long)]
     only_bubbles: bool,
+
+    #[structopt(flatten)]
+    criteria: Criteria,
 }
 
-fn main() {
-    // parse opts
-    let mut opt = Opt::from_args();
-    // if no maps/outputs are explicitly requested, assume a bubble map
-    //
-    // mostly because this one is my favorite
-    if
-        !opt.small_map
-            && !opt.bubble_map
-            && !opt.tile_map
-            && !opt.output.is_some()
-    {
-        opt.bubble_map = true;
+impl Opt {
+    // do we need to run wfc?
+    fn wfc(&self) -> bool {
+        self.tile_map
+            || self.interactive
+            || (self.output.is_some() && !self.only_bubbles)
+            || self.criteria.wfc()
     }
-    let opt = opt;
+}
+
+// the result of generating a station
+struct Generation {
+    seed: u64,
+    decks: Decks,
+    success: bool,
+    full: Vec<bool>,
+    unreachable: Vec<(usize, (isize, isize))>,
+    time: Duration,
+}
 
-    // create our wavestation, this class does most of the work
-    let mut ws = WaveStation::new(
-        opt.seed,
-        None,
-        opt.bubble_p,
-        opt.hallway_p,
-        opt.smallest,
-        opt.clearance,
-        opt.scale,
-        opt.attempts,
-    );
-    println!("seed: 0x{:016x}", ws.seed);
+// how many chunks we grow toward a point without getting any closer before
+// we give up on it, bubbles can wander a bit before finding a way around
+// things, so this is fairly generous
+const TOWARD_STALLS: usize = 32;
+
+// generate a station, this is everything our opts ask for up until
+// rendering
+fn generate(opt: &Opt, seed: u64) -> io::Result<Generation> {
+    let start = Instant::now();
+
+    // learn any patterns and load any mask up front, these are shared by
+    // all decks
+    let overlap = match opt.overlap.is_empty() {
+        true => None,
+        false => Some(Overlap::load(&opt.overlap, opt.overlap_n)?),
+    };
+    let mask = opt.mask.as_ref()
+        .map(|mask| Mask::load(mask, opt.mask_scale))
+        .transpose()?;
+
+    // create our wavestations, one for each deck, this class does most of
+    // the work
+    let mut decks = Decks::new(cmp::max(opt.decks, 1), Some(seed), |seed| {
+        WaveStation::new(
+            seed,
+            None,
+            opt,
+            mask.clone(),
+            overlap.clone(),
+            Budget{
+                deadline: opt.wfc_deadline.map(|deadline| {
+                    start + Duration::from_secs_f64(deadline)
+                }),
+                propagations: opt.wfc_propagations,
+                cancel: None,
+            },
+        )
+    });
 
     // create background thread for animations
     let mut term = if opt.anim_small || opt.anim_bubbles || opt.anim_tiles {
-        Some(BackgroundTerminal::new(
+        let mut term = BackgroundTerminal::new(
             opt.anim_lines,
             opt.anim_sleep.map(|sleep|
                 Duration::from_millis((sleep*1000.0) as u64)
             ),
-        ))
+        );
+        if let Some(record) = &opt.record {
+            term.record(record).unwrap();
+        }
+        if let Some(gif) = &opt.gif {
+            term.record_gif(gif, opt.gif_delay, opt.gif_skip).unwrap();
+        }
+        Some(term)
     } else {
         None
     };
 
     // generate in chunks to avoid wfc failures
     let mut success = true;
+    let mut full = vec![false; decks.decks.len()];
+    // once we reach our size, grow toward any requested points, giving up
+    // if we stop getting closer
+    let mut toward = opt.toward.iter();
+    let mut point = None;
+    let mut closest = vec![f64::INFINITY; decks.decks.len()];
+    let mut stalls = vec![0; decks.decks.len()];
+    let mut unreachable = vec![];
     loop {
         // generate bubbles
-        if opt.size > ws.size {
-            ws.gen_bubbles(cmp::min(opt.chunk_size, opt.size-ws.size));
+        for (i, ws) in decks.decks.iter_mut().enumerate() {
+            if opt.size > ws.size && !full[i] {
+                full[i] = !ws.gen_bubbles(
+                    cmp::min(opt.chunk_size, opt.size-ws.size)
+                );
+            } else if let Some(&point) = point {
+                if stalls[i] < TOWARD_STALLS && !ws.reached(point) {
+                    // nothing left to grow from? give up now
+                    if !ws.gen_toward(point, opt.chunk_size) {
+                        stalls[i] = TOWARD_STALLS;
+                    }
+
+                    let dist = ws.dist_toward(point);
+                    if dist < closest[i] {
+                        closest[i] = dist;
+                        stalls[i] = 0;
+                    } else {
+                        stalls[i] += 1;
+                    }
+                }
+            }
         }
 
+        // connect any new bubbles with lifts
+        decks.gen_lifts();
+
         // render small animation if requested
         if opt.anim_small {
             let term = term.as_mut().unwrap();
-            let (swidth, sheight, smap) = ws.render_small_map(
-                opt.small_width,
-                opt.small_height,
-            );
+            for (i, ws) in decks.decks.iter().enumerate() {
+                if decks.decks.len() > 1 {
+                    writeln!(term, "deck {}:", i).unwrap();
+                }
+                let (swidth, sheight, smap) = ws.render_small_map(
+                    opt.small_width,
+                    opt.small_height,
+                );
 
-            for y in 0..sheight {
-                for x in 0..swidth {
-                    write!(term, "{}",
-                        char::from_u32(smap[x+y*swidth] as u32).unwrap()
-                    ).unwrap();
+                for y in 0..sheight {
+                    for x in 0..swidth {
+                        write!(term, "{}",
+                            smap[x+y*swidth]
+                        ).unwrap();
+                    }
+                    writeln!(term).unwrap();
                 }
-                writeln!(term).unwrap();
             }
 
             term.swap();
@@ -1239,37 +2584,62 @@ fn main() {
         // render bubble animation if requested
         if opt.anim_bubbles {
             let term = term.as_mut().unwrap();
-            let (bwidth, bheight, bmap) = ws.render_bubble_map();
+            for (i, ws) in decks.decks.iter().enumerate() {
+                if decks.decks.len() > 1 {
+                    writeln!(term, "deck {}:", i).unwrap();
+                }
+                let (bwidth, bheight, bmap) = ws.render_bubble_map();
 
-            for y in 0..bheight {
-                for x in 0..bwidth {
-                    write!(term, "{}",
-                        char::from_u32(bmap[x+y*bwidth] as u32).unwrap()
-                    ).unwrap();
+                for y in 0..bheight {
+                    for x in 0..bwidth {
+                        write!(term, "{}",
+                            bmap[x+y*bwidth]
+                        ).unwrap();
+                    }
+                    writeln!(term).unwrap();
                 }
-                writeln!(term).unwrap();
             }
 
             term.swap();
         }
 
-        if opt.tile_map || (opt.output.is_some() && !opt.only_bubbles) {
+        if opt.wfc() {
             // perform wfc on any new bubbles
             //
             // new bubbles may come from initialization!
-            success = ws.wfc(
-                if opt.anim_tiles {
-                    Some(term.as_mut().unwrap())
-                } else {
-                    None
-                },
-                opt.tile_sleep.map(|sleep|
-                    Duration::from_millis((sleep*1000.0) as u64)
-                ),
-            );
+            for ws in decks.decks.iter_mut() {
+                success = ws.wfc(
+                    if opt.anim_tiles {
+                        Some(term.as_mut().unwrap())
+                    } else {
+                        None
+                    },
+                    opt.tile_sleep.map(|sleep|
+                        Duration::from_millis((sleep*1000.0) as u64)
+                    ),
+                ) && ws.meet_targets(
+                    if opt.anim_tiles {
+                        Some(term.as_mut().unwrap())
+                    } else {
+                        None
+                    },
+                    opt.tile_sleep.map(|sleep|
+                        Duration::from_millis((sleep*1000.0) as u64)
+                    ),
+                );
+                if !success {
+                    break;
+                }
+            }
             if !success {
                 break;
             }
+
+            // out of budget? our fallback has filled in a partial result,
+            // anything we grow from here would only be fallback tiles
+            if decks.decks.iter().any(|ws| ws.wfc_exhausted) {
+                break;
+            }
         }
 
         // sleep after bubble generation if requested
@@ -1277,96 +2647,311 @@ fn main() {
             thread::sleep(Duration::from_millis((sleep*1000.0) as u64));
         }
 
-        if ws.size >= opt.size {
-            break;
+        if
+            decks.decks.iter().zip(&full)
+                .all(|(ws, &full)| ws.size >= opt.size || full)
+                && decks.decks.iter().zip(&stalls)
+                    .all(|(ws, &stalls)| {
+                        point.is_none_or(|&point| {
+                            stalls >= TOWARD_STALLS || ws.reached(point)
+                        })
+                    })
+        {
+            // move on to the next point, freezing anything we've seen
+            // where we are
+            for (i, ws) in decks.decks.iter_mut().enumerate() {
+                match point {
+                    Some(&point) => {
+                        if !ws.reached(point) {
+                            unreachable.push((i, point));
+                        }
+                        ws.freeze(point, opt.view);
+                    }
+                    None if !opt.toward.is_empty() => {
+                        ws.freeze((0, 0), opt.view);
+                    }
+                    None => {}
+                }
+            }
+            point = toward.next();
+            closest.fill(f64::INFINITY);
+            stalls.fill(0);
+            if point.is_none() {
+                break;
+            }
+        }
+    }
+
+    // apply any edits, note bubbles/hallways are indexed as they were
+    // generated, before any edits
+    if
+        success
+            && (!opt.remove.is_empty()
+                || !opt.remove_hallway.is_empty()
+                || !opt.reroll.is_empty()
+                || !opt.reroll_region.is_empty())
+    {
+        let deck = cmp::min(opt.edit_deck, decks.decks.len()-1);
+        let bubbles = decks.decks[deck].bubbles.clone();
+        let hallways = decks.decks[deck].hallways.clone();
+        for &i in &opt.remove {
+            if let Some(bubble) = bubbles.get(i) {
+                decks.remove_bubble(deck, bubble);
+            }
+        }
+        for &i in &opt.remove_hallway {
+            if let Some((a, b)) = hallways.get(i) {
+                decks.remove_hallway(deck, a, b);
+            }
+        }
+        for &i in &opt.reroll {
+            if let Some(bubble) = bubbles.get(i) {
+                // skip bubbles we've already removed
+                if decks.decks[deck].bubbles.iter()
+                    .any(|bubble_| Rc::ptr_eq(bubble_, bubble))
+                {
+                    decks.decks[deck].reroll_bubble(bubble);
+                }
+            }
+        }
+        for &(x, y, width, height) in &opt.reroll_region {
+            decks.decks[deck].reroll_region(x, y, width, height);
+        }
+
+        // and resolve what we changed
+        if opt.wfc() {
+            for ws in decks.decks.iter_mut() {
+                success = ws.wfc(
+                    if opt.anim_tiles {
+                        Some(term.as_mut().unwrap())
+                    } else {
+                        None
+                    },
+                    opt.tile_sleep.map(|sleep|
+                        Duration::from_millis((sleep*1000.0) as u64)
+                    ),
+                ) && ws.meet_targets(
+                    if opt.anim_tiles {
+                        Some(term.as_mut().unwrap())
+                    } else {
+                        None
+                    },
+                    opt.tile_sleep.map(|sleep|
+                        Duration::from_millis((sleep*1000.0) as u64)
+                    ),
+                );
+                if !success {
+                    break;
+                }
+            }
         }
     }
 
     // cleanup background terminal for animations here
     drop(term);
 
-    // one last wfc to make sure things are cleaned up
-
-    // print stats
-    println!("gen: {}x{} cells, {} bubbles",
-        ws.width,
-        ws.height,
-        ws.bubbles.len()
-    );
-    println!("in: {} cycles, {:?}",
-        ws.bubble_cycles,
-        ws.bubble_time
-    );
-    println!("wfc: {}x{} tiles, {} constraints",
-        ws.cwidth, ws.cheight,
-        // note each tile has 4 directional constraints
-        TILES.len()*4
-    );
-    println!("in: {}/{} attempts, {} cycles, {} propagations, {:?}",
-        ws.wfc_attempts,
-        opt.attempts,
-        ws.wfc_cycles,
-        ws.wfc_propagations,
-        ws.wfc_time,
-    );
-
-    // render small map
-    if opt.small_map {
-        let (swidth, sheight, smap) = ws.render_small_map(
-            opt.small_width,
-            opt.small_height,
-        );
+    Ok(Generation{
+        seed,
+        decks,
+        success,
+        full,
+        unreachable,
+        time: start.elapsed(),
+    })
+}
 
-        for y in 0..sheight {
-            for x in 0..swidth {
-                print!("{}",
-                    char::from_u32(smap[x+y*swidth] as u32).unwrap()
-                );
-            }
-            println!();
+// report an error and exit, rather than panicking on things like bad
+// paths
+fn or_exit<T>(result: io::Result<T>) -> T {
+    match result {
+        Ok(t) => t,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
         }
     }
+}
 
-    // render bubble map
-    if opt.bubble_map {
-        let (bwidth, bheight, bmap) = ws.render_bubble_map();
+fn main() {
+    // parse opts, batch and adjacency modes are subcommands with their own
+    // opts, which is why we can't require a size if either is given
+    let matches = Opt::clap()
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(BatchOpt::clap())
+        .subcommand(AdjacencyOpt::clap())
+        .get_matches();
+    match matches.subcommand() {
+        ("batch", Some(matches)) => {
+            or_exit(batch(BatchOpt::from_clap(matches)));
+            return;
+        }
+        ("adjacency", Some(matches)) => {
+            or_exit(adjacency(AdjacencyOpt::from_clap(matches)));
+            return;
+        }
+        _ => {}
+    }
 
-        for y in 0..bheight {
-            for x in 0..bwidth {
-                print!("{}",
-                    char::from_u32(bmap[x+y*bwidth] as u32).unwrap()
-                );
-            }
-            println!();
+    let mut opt = Opt::from_clap(&matches);
+    // if no maps/outputs are explicitly requested, assume a bubble map
+    //
+    // mostly because this one is my favorite
+    if
+        !opt.small_map
+            && !opt.bubble_map
+            && !opt.tile_map
+            && opt.output.is_none()
+            && !opt.interactive
+    {
+        opt.bubble_map = true;
+    }
+    let opt = opt;
+
+    // initialize with either provided seed or actually random seed
+    let seed = opt.seed.unwrap_or_else(|| {
+        rand::thread_rng().next_u64()
+    });
+    eprintln!("seed: 0x{:016x}", seed);
+
+    let (generation, rejected) = or_exit(generate_accepted(&opt, seed));
+    for (seed, reason) in &rejected {
+        eprintln!("rejected 0x{:016x}: {}", seed, reason);
+    }
+    if !rejected.is_empty() {
+        // if we gave up, our last generation was also rejected
+        if rejected.last().map(|&(seed, _)| seed) != Some(generation.seed) {
+            eprintln!("accepted seed: 0x{:016x}", generation.seed);
+        } else {
+            eprintln!("no seed accepted after {} re-rolls!",
+                opt.criteria.max_rerolls
+            );
         }
     }
 
-    // render tile
-    if opt.tile_map {
-        let (twidth, theight, tmap) = ws.render_tile_map();
+    // write stats to json if requested
+    if let Some(stats_json) = &opt.stats_json {
+        let mut f = File::create(stats_json).unwrap();
+        serde_json::to_writer(&mut f, &GenerationReport::new(&generation))
+            .unwrap();
+    }
 
-        for y in 0..theight {
-            for x in 0..twidth {
-                print!("{}",
-                    char::from_u32(tmap[x+y*twidth] as u32).unwrap()
-                );
-            }
-            println!();
+    let Generation{decks, success, full, unreachable, ..} = generation;
+    let color = opt.color.enabled();
+
+    for (i, ws) in decks.decks.iter().enumerate() {
+        if decks.decks.len() > 1 {
+            println!("deck {}:", i);
+        }
+
+        // print stats, these go to stderr so stdout is just our maps
+        eprintln!("gen: {}x{} cells, {} bubbles",
+            ws.width,
+            ws.height,
+            ws.bubbles.len()
+        );
+        if full[i] {
+            eprintln!("mask full at size {}/{}", ws.size, opt.size);
+        }
+        for &(_, (x, y)) in unreachable.iter().filter(|(j, _)| *j == i) {
+            eprintln!("could not reach {},{}", x, y);
+        }
+        eprintln!("in: {} cycles, {:?}",
+            ws.bubble_cycles,
+            ws.bubble_time
+        );
+        eprintln!("wfc: {}x{} tiles, {} constraints",
+            ws.cwidth, ws.cheight,
+            // note each tile has 4 directional constraints
+            TILES.len()*4
+        );
+        if let Some(overlap) = &ws.overlap {
+            eprintln!("overlap: {} patterns", overlap.patterns());
+        }
+        eprintln!("in: {}/{} attempts, {} cycles, {} propagations, {:?}",
+            ws.wfc_attempts,
+            opt.attempts,
+            ws.wfc_cycles,
+            ws.wfc_propagations,
+            ws.wfc_time,
+        );
+        if ws.wfc_repairs > 0 {
+            eprintln!("repaired {} contradictions", ws.wfc_repairs);
+        }
+        if !ws.targets.is_empty() {
+            eprintln!("targets: {} bubbles re-solved, {} missed",
+                ws.target_rerolled,
+                ws.target_missed()
+            );
+        }
+        if ws.wfc_exhausted {
+            eprintln!("out of wfc budget, {} tiles filled by fallback, \
+                    {} breaking constraints",
+                ws.wfc_fallbacks,
+                ws.wfc_breaches
+            );
+        }
+        if i > 0 {
+            eprintln!("lifts: {}",
+                decks.lifts.iter().filter(|lift| lift.deck == i-1).count()
+            );
+        }
+
+        // render small map
+        if opt.small_map {
+            let (swidth, sheight, smap) = ws.render_small_map(
+                opt.small_width,
+                opt.small_height,
+            );
+
+            print_map(swidth, sheight, &smap, None);
+        }
+
+        // render bubble map
+        if opt.bubble_map {
+            let (bwidth, bheight, bmap) = ws.render_bubble_map();
+            let styles = color.then(|| ws.style_bubble_map());
+            print_map(bwidth, bheight, &bmap, styles.as_deref());
+        }
+
+        // render tile
+        if opt.tile_map {
+            let (twidth, theight, tmap) = ws.render_tile_map();
+            let styles = color.then(|| ws.style_tile_map());
+            print_map(twidth, theight, &tmap, styles.as_deref());
         }
     }
 
     // write to json if requested
     if success {
-        if let Some(output) = opt.output {
-            let mut f = File::create(&output).unwrap();
-            serde_json::to_writer(&mut f, &ws).unwrap();
-            println!("updated {:?}", output);
+        if let Some(output) = &opt.output {
+            let mut f = File::create(output).unwrap();
+            // a single deck is written as-is, multiple decks are written
+            // along with their lifts
+            if decks.decks.len() > 1 {
+                serde_json::to_writer(&mut f, &decks).unwrap();
+            } else {
+                serde_json::to_writer(&mut f, &decks.decks[0]).unwrap();
+            }
+            eprintln!("updated {:?}", output);
         }
     }
 
     if !success {
-        println!("failed to resolve constraints after {} attempts!",
-            opt.attempts
-        );
+        if decks.decks.iter().any(|ws| ws.wfc_exhausted) {
+            eprintln!("ran out of wfc budget!");
+        } else {
+            eprintln!("failed to resolve constraints after {} attempts!",
+                opt.attempts
+            );
+        }
+    }
+
+    // explore interactively?
+    if opt.interactive {
+        let mut viewer = Viewer::new(decks, opt.small_width, opt.small_height);
+        or_exit(viewer.run(|| {
+            generate_accepted(&opt, rand::thread_rng().next_u64())
+                .map(|(generation, _)| generation.decks)
+        }));
     }
 }
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+
+// a target silhouette for station generation
+//
+// this is described as a signed distance function, negative inside the
+// mask and positive outside, in bubble cells relative to the root bubble
+//
+#[derive(Clone)]
+pub struct Mask {
+    sdf: Arc<dyn Fn(f64, f64) -> f64 + Send + Sync>,
+}
+
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mask").finish_non_exhaustive()
+    }
+}
+
+impl Mask {
+    // create a mask from an arbitrary signed distance function, note the
+    // root bubble is always placed at 0,0, so this should be inside the
+    // mask
+    pub fn from_sdf<F>(sdf: F) -> Self
+    where
+        F: Fn(f64, f64) -> f64 + Send + Sync + 'static
+    {
+        Self{sdf: Arc::new(sdf)}
+    }
+
+    // create a mask from a bitmap, each pixel is scale bubble cells
+    //
+    // the root bubble is placed at origin if provided, otherwise at the
+    // inside pixel closest to the center of the bitmap
+    //
+    pub fn from_bitmap(
+        width: usize,
+        height: usize,
+        bitmap: Vec<bool>,
+        origin: Option<(usize, usize)>,
+        scale: usize,
+    ) -> Self {
+        debug_assert!(bitmap.len() == width*height);
+
+        let origin = origin.or_else(|| {
+            (0..width*height)
+                .filter(|&i| bitmap[i])
+                .map(|i| (i % width, i / width))
+                .min_by_key(|&(x, y)| {
+                    let d_x = 2*x as isize - width as isize;
+                    let d_y = 2*y as isize - height as isize;
+                    d_x*d_x + d_y*d_y
+                })
+        }).unwrap_or((width/2, height/2));
+
+        // find pixels on the border of the mask, these are what we
+        // measure distance against
+        let inside = |x: isize, y: isize| {
+            x >= 0 && x < width as isize
+                && y >= 0 && y < height as isize
+                && bitmap[x as usize + y as usize*width]
+        };
+        let mut border = vec![];
+        for y in -1..height as isize+1 {
+            for x in -1..width as isize+1 {
+                let c = inside(x, y);
+                if
+                    inside(x-1, y) != c
+                        || inside(x+1, y) != c
+                        || inside(x, y-1) != c
+                        || inside(x, y+1) != c
+                {
+                    border.push((x, y, c));
+                }
+            }
+        }
+
+        // precompute the distance field, this is a bit naive, but masks
+        // are small
+        let mut field = vec![0.0; width*height];
+        for y in 0..height {
+            for x in 0..width {
+                let c = bitmap[x+y*width];
+                let mut d = f64::INFINITY;
+                for &(x_, y_, c_) in &border {
+                    if c_ != c {
+                        let d_x = x_ as f64 - x as f64;
+                        let d_y = y_ as f64 - y as f64;
+                        d = d.min((d_x*d_x + d_y*d_y).sqrt());
+                    }
+                }
+                // measure from pixel edges rather than centers
+                field[x+y*width] = if c { 0.5-d } else { d-0.5 };
+            }
+        }
+
+        let scale = scale as f64;
+        Self::from_sdf(move |x, y| {
+            let x = x/scale + origin.0 as f64;
+            let y = y/scale + origin.1 as f64;
+            let x_ = x.round();
+            let y_ = y.round();
+            if
+                x_ >= 0.0 && x_ < width as f64
+                    && y_ >= 0.0 && y_ < height as f64
+            {
+                field[x_ as usize + y_ as usize*width] * scale
+            } else {
+                // outside the bitmap, estimate with distance to the
+                // bitmap's edges
+                let d_x = (-0.5-x).max(x-(width as f64-0.5)).max(0.0);
+                let d_y = (-0.5-y).max(y-(height as f64-0.5)).max(0.0);
+                ((d_x*d_x + d_y*d_y).sqrt() + 0.5) * scale
+            }
+        })
+    }
+
+    // parse a mask from ascii art, any non-whitespace character is
+    // inside the mask, and an optional 'o' marks where the root bubble
+    // should go
+    pub fn from_ascii(ascii: &str, scale: usize) -> Self {
+        let lines = ascii.lines().collect::<Vec<_>>();
+        let width = lines.iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = lines.len();
+
+        let mut bitmap = vec![false; width*height];
+        let mut origin = None;
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                bitmap[x+y*width] = !c.is_whitespace();
+                if c == 'o' {
+                    origin = Some((x, y));
+                }
+            }
+        }
+
+        Self::from_bitmap(width, height, bitmap, origin, scale)
+    }
+
+    // decode a mask from a png, dark opaque pixels are inside the mask
+    pub fn from_png<R: Read>(reader: R, scale: usize) -> io::Result<Self> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(
+            png::Transformations::EXPAND | png::Transformations::STRIP_16
+        );
+        let mut reader = decoder.read_info()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let channels = info.color_type.samples();
+        let mut bitmap = vec![false; width*height];
+        for i in 0..width*height {
+            let px = &buf[i*channels .. (i+1)*channels];
+            let (luma, alpha) = match px.len() {
+                1 => (px[0] as u32, 255),
+                2 => (px[0] as u32, px[1] as u32),
+                3 => ((px[0] as u32 + px[1] as u32 + px[2] as u32) / 3, 255),
+                _ => ((px[0] as u32 + px[1] as u32 + px[2] as u32) / 3,
+                    px[3] as u32),
+            };
+            bitmap[i] = alpha >= 128 && luma < 128;
+        }
+
+        Ok(Self::from_bitmap(width, height, bitmap, None, scale))
+    }
+
+    // load a mask from either a png or ascii file
+    pub fn load<P: AsRef<Path>>(path: P, scale: usize) -> io::Result<Self> {
+        let path = path.as_ref();
+        let load = || if path.extension().is_some_and(|ext| ext == "png") {
+            Self::from_png(File::open(path)?, scale)
+        } else {
+            let mut ascii = String::new();
+            File::open(path)?.read_to_string(&mut ascii)?;
+            Ok(Self::from_ascii(&ascii, scale))
+        };
+
+        // note which mask we failed to load
+        load().map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+        })
+    }
+
+    // signed distance to the edge of the mask
+    pub fn dist(&self, x: isize, y: isize) -> f64 {
+        (self.sdf)(x as f64, y as f64)
+    }
+
+    // does a circle fit entirely inside the mask?
+    pub fn contains(&self, x: isize, y: isize, r: usize) -> bool {
+        self.dist(x, y) <= -(r as f64)
+    }
+}
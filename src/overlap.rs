@@ -0,0 +1,551 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::*;
+
+
+// a pattern extracted from our examples, n*n tile masks
+#[derive(Debug)]
+struct Pattern {
+    tiles: Vec<u128>,
+    // how often this pattern shows up in our examples
+    weight: usize,
+    // which patterns can sit next to this one in each direction, as a
+    // bitset, these must agree wherever they overlap
+    compat: [Vec<u64>; 4],
+}
+
+// parse a tile map drawn in the same style render_tile_map produces,
+// padded with space, into constraints
+//
+// since several tiles may share a glyph, this returns the set of tiles
+// each glyph could be
+//
+pub fn parse_tile_map(
+    ascii: &str,
+    pad: usize,
+) -> Result<(usize, usize, Vec<u128>), String> {
+    // build a lookup from glyphs to tile masks, in either charset
+    let mut glyphs: HashMap<[char; 2], u128> = HashMap::new();
+    for (i, tile) in TILES.iter().enumerate() {
+        for charset in [Charset::Ascii, Charset::Unicode] {
+            *glyphs.entry(tile.glyphs(charset)).or_insert(0) |= 1 << i;
+        }
+    }
+    let blank = glyphs[&[' ', ' ']];
+
+    let lines = ascii.lines()
+        .map(|line| line.trim_end().chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let width = lines.iter()
+        .map(|line| line.len().div_ceil(2))
+        .max()
+        .unwrap_or(0);
+    let height = lines.len();
+
+    let width_ = width + 2*pad;
+    let height_ = height + 2*pad;
+    let mut tmap = vec![blank; width_*height_];
+    for (y, line) in lines.iter().enumerate() {
+        for x in 0..line.len().div_ceil(2) {
+            let glyph = [line[2*x], line.get(2*x+1).copied().unwrap_or(' ')];
+            tmap[(x+pad)+(y+pad)*width_] = match glyphs.get(&glyph) {
+                Some(&tiles) => tiles,
+                // note tiles are always two chars wide
+                None => return Err(format!(
+                    "unknown tile {:?} at {}:{}, \
+                        tiles should start at odd columns",
+                    glyph.iter().collect::<String>(),
+                    y+1, 2*x+1,
+                )),
+            };
+        }
+    }
+
+    // blanks are either space or floor, we can tell these apart by whether
+    // they're reachable from outside our map
+    let mut outside = vec![false; width_*height_];
+    let mut stack = vec![];
+    for y in 0..height_ {
+        for x in 0..width_ {
+            if x == 0 || x == width_-1 || y == 0 || y == height_-1 {
+                stack.push((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = stack.pop() {
+        if outside[x+y*width_] || tmap[x+y*width_] != blank {
+            continue;
+        }
+        outside[x+y*width_] = true;
+        if x > 0 { stack.push((x-1, y)); }
+        if y > 0 { stack.push((x, y-1)); }
+        if x < width_-1 { stack.push((x+1, y)); }
+        if y < height_-1 { stack.push((x, y+1)); }
+    }
+    for i in 0..width_*height_ {
+        if tmap[i] == blank {
+            tmap[i] = match outside[i] {
+                true  => blank & TILE_SPACEISH,
+                false => blank & !TILE_SPACEISH,
+            };
+        }
+    }
+
+    Ok((width_, height_, tmap))
+}
+
+// an overlapping model, this learns patterns from example maps drawn in
+// the same style render_tile_map produces, and uses them to fill in
+// bubble and hallway interiors
+//
+// since a glyph may be shared by several tiles, patterns only narrow down
+// each tile to whatever tiles share its glyph, we still need a pass of
+// simple tiled wfc afterwards to pick consistent tiles
+//
+#[derive(Debug, Clone)]
+pub struct Overlap {
+    n: usize,
+    patterns: Arc<Vec<Pattern>>,
+}
+
+// how many contradictions we repair when resolving tiles from patterns
+const OVERLAP_REPAIR: usize = 16;
+
+// directions in the order we store compat, and their offsets
+const DIRS: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+impl Overlap {
+    // learn n*n patterns from some example maps
+    pub fn from_ascii(examples: &[&str], n: usize) -> Result<Self, String> {
+        if n == 0 {
+            return Err("patterns must be at least 1x1".into());
+        }
+
+        // note we keep patterns in the order we find them so our results
+        // only depend on our seed
+        let mut patterns: Vec<(Vec<u128>, usize)> = vec![];
+        let mut indices: HashMap<Vec<u128>, usize> = HashMap::new();
+        for example in examples {
+            // anything outside our example is space, so pad with blanks to
+            // pick up patterns along the edges
+            //
+            // note degenerate tiles also look like space, but treating them
+            // as space keeps outside patterns from fitting inside bubbles
+            let (width_, height_, mut tmap) = parse_tile_map(example, n-1)?;
+            for c in tmap.iter_mut() {
+                if *c & TILE_SPACE != 0 {
+                    *c = TILE_SPACE;
+                }
+            }
+
+            for y in 0..height_+1-n {
+                for x in 0..width_+1-n {
+                    let pattern = (0..n*n)
+                        .map(|i| tmap[(x + i%n)+(y + i/n)*width_])
+                        .collect::<Vec<_>>();
+                    match indices.get(&pattern) {
+                        Some(&i) => patterns[i].1 += 1,
+                        None => {
+                            indices.insert(pattern.clone(), patterns.len());
+                            patterns.push((pattern, 1));
+                        }
+                    }
+                }
+            }
+        }
+
+        if patterns.is_empty() {
+            return Err("no patterns in examples".into());
+        }
+
+        // find which patterns agree when they overlap
+        let words = patterns.len().div_ceil(64);
+        let agree = |a: &[u128], b: &[u128], (d_x, d_y): (isize, isize)| {
+            (0..n*n).all(|i| {
+                let x = (i%n) as isize - d_x;
+                let y = (i/n) as isize - d_y;
+                x < 0 || x >= n as isize || y < 0 || y >= n as isize
+                    || a[i] == b[x as usize + y as usize*n]
+            })
+        };
+        let patterns = patterns.iter()
+            .map(|(tiles, weight)| {
+                let compat = DIRS.map(|dir| {
+                    let mut compat = vec![0u64; words];
+                    for (j, (tiles_, _)) in patterns.iter().enumerate() {
+                        if agree(tiles, tiles_, dir) {
+                            compat[j/64] |= 1 << (j%64);
+                        }
+                    }
+                    compat
+                });
+                Pattern{
+                    tiles: tiles.clone(),
+                    weight: *weight,
+                    compat,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self{n, patterns: Arc::new(patterns)})
+    }
+
+    // load examples from ascii files
+    pub fn load<P: AsRef<Path>>(paths: &[P], n: usize) -> io::Result<Self> {
+        let mut examples = vec![];
+        for path in paths {
+            let path = path.as_ref();
+            let mut ascii = String::new();
+            File::open(path)
+                .and_then(|mut f| f.read_to_string(&mut ascii))
+                // note which example we failed to load
+                .map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("{}: {}", path.display(), e),
+                    )
+                })?;
+            examples.push(ascii);
+        }
+
+        Self::from_ascii(
+            &examples.iter().map(|ascii| ascii.as_str()).collect::<Vec<_>>(),
+            n,
+        ).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn patterns(&self) -> usize {
+        self.patterns.len()
+    }
+
+    // which pattern does a cell resolve to? note this is the first pattern
+    // if there are several
+    fn pattern(&self, domain: &[u64]) -> usize {
+        (0..domain.len())
+            .find(|&w| domain[w] != 0)
+            .map(|w| w*64 + domain[w].trailing_zeros() as usize)
+            .unwrap()
+    }
+
+    // propagate constraints between neighboring patterns, these must agree
+    // wherever they overlap
+    #[allow(clippy::too_many_arguments)]
+    fn propagate(
+        &self,
+        wfc: &Wfc,
+        domains: &mut [u64],
+        trail: &mut Trail<u64>,
+        resolving: &[bool],
+        unresolved: &mut ConstraintSet,
+        propagating: &mut Vec<(usize, usize)>,
+        stats: &mut WfcStats,
+    ) -> Propagated {
+        let (width, height) = (wfc.width, wfc.height);
+        let words = self.patterns.len().div_ceil(64);
+        let mut mask = vec![0u64; words];
+        while let Some((x, y)) = propagating.pop() {
+            // dropped cells don't constrain anything
+            let i = x+y*width;
+            if !resolving[i] {
+                continue;
+            }
+
+            // out of budget? stop here with a partial result
+            if wfc.budget.exceeded(stats.propagations) {
+                return Propagated::Exhausted;
+            }
+            stats.propagations += 1;
+
+            // for each neighbor
+            for (d, &(d_x, d_y)) in DIRS.iter().enumerate() {
+                let x_ = x as isize + d_x;
+                let y_ = y as isize + d_y;
+                if
+                    x_ < 0 || x_ >= width as isize
+                        || y_ < 0 || y_ >= height as isize
+                        || !resolving[x_ as usize+y_ as usize*width]
+                {
+                    continue;
+                }
+                let (x_, y_) = (x_ as usize, y_ as usize);
+                let i_ = x_+y_*width;
+
+                // what do our patterns allow our neighbor to be?
+                mask.fill(0);
+                for w in 0..words {
+                    let mut bits = domains[i*words + w];
+                    while bits != 0 {
+                        let j = w*64 + bits.trailing_zeros() as usize;
+                        bits &= bits - 1;
+                        for (m, c) in mask.iter_mut()
+                            .zip(&self.patterns[j].compat[d])
+                        {
+                            *m |= c;
+                        }
+                    }
+                }
+
+                // did we actually change anything?
+                let before = count(&domains[i_*words..(i_+1)*words]);
+                for (w, m) in mask.iter().enumerate() {
+                    let c = domains[i_*words + w];
+                    if c & m != c {
+                        trail.set(domains, i_*words + w, c & m);
+                    }
+                }
+                let after = count(&domains[i_*words..(i_+1)*words]);
+                if after != before {
+                    if after == 0 {
+                        return Propagated::Contradiction(x_, y_);
+                    }
+                    // move into different bucket
+                    unresolved.remove(before, x_, y_);
+                    unresolved.insert(after, x_, y_);
+                    // propagate constraints to our neighbors
+                    propagating.push((x_, y_));
+                }
+            }
+        }
+
+        Propagated::Done
+    }
+
+    // fill in any unresolved constraints in cmap with our patterns, and
+    // then resolve the actual tiles with simple tiled wfc
+    //
+    // this returns false if we couldn't find a solution in our attempts
+    pub fn solve(
+        &self,
+        wfc: &Wfc,
+        cmap: &mut [u128],
+        prng: &mut Xorshift64,
+        stats: &mut WfcStats,
+        mut anim: Anim<'_>,
+    ) -> bool {
+        let (width, height, n) = (wfc.width, wfc.height, self.n);
+        let words = self.patterns.len().div_ceil(64);
+
+        // log any changes we make to cmap and our domains, this allows us
+        // to quickly revert failed attempts
+        let mut trail = Trail::new();
+        let mut domain_trail = Trail::new();
+
+        // figure out what we actually need to resolve, and which patterns
+        // fit each cell to start with
+        let init_cmap = cmap.to_vec();
+        let mut domains = vec![0u64; width*height*words];
+        let mut resolving = vec![false; width*height];
+        for y in 0..height {
+            for x in 0..width {
+                let live = wfc.live.is_none_or(|live| live[x+y*width]);
+                if cmap[x+y*width].count_ones() <= 1 || !live {
+                    continue;
+                }
+
+                // patterns are centered on their cell, so they see all of
+                // their neighbors
+                for (j, pattern) in self.patterns.iter().enumerate() {
+                    if (0..n*n).all(|i| {
+                        let x_ = (x + i%n).wrapping_sub(n/2);
+                        let y_ = (y + i/n).wrapping_sub(n/2);
+                        x_ >= width || y_ >= height
+                            || pattern.tiles[i] & cmap[x_+y_*width] != 0
+                    }) {
+                        domains[(x+y*width)*words + j/64]
+                            |= 1 << (j%64);
+                        resolving[x+y*width] = true;
+                    }
+                }
+            }
+        }
+
+        // our examples may not cover everything, so propagate our initial
+        // patterns up front, leaving anything that can't fit a pattern to
+        // simple tiled wfc
+        //
+        // note we need to start over after dropping cells, since they may
+        // have constrained their neighbors
+        loop {
+            domain_trail.rollback(&mut domains, 0);
+            let mut propagating = (0..width*height)
+                .filter(|&i| resolving[i])
+                .map(|i| (i % width, i / width))
+                .collect::<Vec<_>>();
+            let mut dropped = false;
+            loop {
+                match self.propagate(
+                    wfc,
+                    &mut domains,
+                    &mut domain_trail,
+                    &resolving,
+                    &mut ConstraintSet::new(),
+                    &mut propagating,
+                    stats,
+                ) {
+                    Propagated::Done => break,
+                    Propagated::Exhausted => {
+                        stats.exhausted = true;
+                        return false;
+                    }
+                    Propagated::Contradiction(x, y) => {
+                        resolving[x+y*width] = false;
+                        dropped = true;
+                    }
+                }
+            }
+
+            if !dropped {
+                break;
+            }
+        }
+        let init_domains = domain_trail.checkpoint();
+        let init_unresolved = (0..width*height)
+            .filter(|&i| resolving[i])
+            .map(|i| (i % width, i / width))
+            .collect::<Vec<_>>();
+
+        let mut attempts = 0;
+        let mut success = false;
+        'wfc: for _ in 0..wfc.attempts {
+            if wfc.budget.exceeded(stats.propagations) {
+                stats.exhausted = true;
+                break;
+            }
+            attempts += 1;
+
+            // reset to our initial maps
+            trail.rollback(cmap, 0);
+            domain_trail.rollback(&mut domains, init_domains);
+
+            // keep track of all unresolved cells
+            let mut unresolved = ConstraintSet::new();
+            for &(x, y) in &init_unresolved {
+                let i = x+y*width;
+                let count = count(&domains[i*words..(i+1)*words]);
+                if count > 1 {
+                    unresolved.insert(count, x, y);
+                }
+            }
+            let mut propagating = vec![];
+
+            // core wfc algorithm, this time over patterns
+            loop {
+                stats.cycles += 1;
+
+                // propagate new constraints
+                match self.propagate(
+                    wfc,
+                    &mut domains,
+                    &mut domain_trail,
+                    &resolving,
+                    &mut unresolved,
+                    &mut propagating,
+                    stats,
+                ) {
+                    Propagated::Done => {}
+                    Propagated::Exhausted => {
+                        stats.exhausted = true;
+                        break 'wfc;
+                    }
+                    // contradiction? abort the current wfc
+                    Propagated::Contradiction(_, _) => continue 'wfc,
+                }
+
+                // do we have unresolved patterns? choose the most-resolved
+                while propagating.is_empty() {
+                    match unresolved.pop(prng) {
+                        Some((_, x, y)) => {
+                            let i = x+y*width;
+                            let domain = &domains[i*words..(i+1)*words];
+                            if count(domain) <= 1 {
+                                continue;
+                            }
+
+                            // randomly choose a pattern, weighted by how
+                            // often it shows up in our examples
+                            let choices = (0..self.patterns.len())
+                                .filter(|&j| domain[j/64] & (1 << (j%64)) != 0)
+                                .collect::<Vec<_>>();
+                            let mut p = prng.next() * choices.iter()
+                                .map(|&j| self.patterns[j].weight)
+                                .sum::<usize>() as f64;
+                            let mut choice = 0;
+                            while
+                                choice < choices.len()-1
+                                    && p >= self.patterns[choices[choice]]
+                                        .weight as f64
+                            {
+                                p -= self.patterns[choices[choice]]
+                                    .weight as f64;
+                                choice += 1;
+                            }
+
+                            let j = choices[choice];
+                            for w in 0..words {
+                                domain_trail.set(
+                                    &mut domains, i*words + w,
+                                    if w == j/64 { 1 << (j%64) } else { 0 },
+                                );
+                            }
+                            // propagate constraints to our neighbors
+                            propagating.push((x, y));
+                        }
+                        None => {
+                            // narrow each cell down to the tiles that share
+                            // its pattern's glyph
+                            for &(x, y) in &init_unresolved {
+                                let i = x+y*width;
+                                let j = self.pattern(
+                                    &domains[i*words..(i+1)*words]
+                                );
+                                trail.set(
+                                    cmap, i,
+                                    cmap[i] & self.patterns[j]
+                                        .tiles[n/2 + (n/2)*n],
+                                );
+                            }
+
+                            // and resolve the actual tiles, our patterns
+                            // don't know about our tile constraints, so we
+                            // repair any contradictions by re-solving
+                            // without patterns, if this still fails try
+                            // again with different patterns
+                            let tiled = Wfc{
+                                attempts: 1,
+                                repair: cmp::max(wfc.repair, OVERLAP_REPAIR),
+                                ..*wfc
+                            };
+                            let reanim = anim.as_mut().map(|anim| {
+                                &mut **anim as &mut dyn FnMut(&[u128])
+                            });
+                            if tiled.solve_from(
+                                cmap, &init_cmap, &mut trail,
+                                prng, stats, reanim,
+                            ) {
+                                success = true;
+                                break 'wfc;
+                            }
+                            if stats.exhausted {
+                                break 'wfc;
+                            }
+                            continue 'wfc;
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.attempts = cmp::max(stats.attempts, attempts);
+        success
+    }
+}
+
+// how many patterns are in a domain?
+fn count(domain: &[u64]) -> u32 {
+    domain.iter().map(|w| w.count_ones()).sum()
+}
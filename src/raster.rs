@@ -0,0 +1,309 @@
+use std::borrow::Cow;
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::str;
+
+use crate::constraints::*;
+
+
+// a tiny built-in 5x7 bitmap font, each row is 5 bits with the msb on the
+// left, only covering the characters our maps and animations actually use
+const FONT: &[(u8, [u8; 7])] = &[
+    (b' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    (b'-', [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00]),
+    (b'=', [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00]),
+    (b'|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    (b'+', [0x04, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x04]),
+    (b'.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c]),
+    (b'\'', [0x0c, 0x0c, 0x04, 0x00, 0x00, 0x00, 0x00]),
+    (b'"', [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00]),
+    (b'#', [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a]),
+    (b'o', [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e]),
+    (b'[', [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e]),
+    (b']', [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e]),
+    (b'!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    (b'?', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    (b':', [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00]),
+    (b'/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    (b',', [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08]),
+    (b'0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    (b'1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    (b'2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    (b'3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    (b'4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    (b'5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    (b'6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    (b'7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    (b'8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    (b'9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    (b'a', [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f]),
+    (b'b', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e]),
+    (b'c', [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e]),
+    (b'd', [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f]),
+    (b'e', [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e]),
+    (b'f', [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08]),
+    (b'g', [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e]),
+    (b'h', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    (b'i', [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e]),
+    (b'j', [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c]),
+    (b'k', [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12]),
+    (b'l', [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    (b'm', [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11]),
+    (b'n', [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    (b'p', [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10]),
+    (b'q', [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01]),
+    (b'r', [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10]),
+    (b's', [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e]),
+    (b't', [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06]),
+    (b'u', [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d]),
+    (b'v', [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    (b'w', [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a]),
+    (b'x', [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11]),
+    (b'y', [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e]),
+    (b'z', [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f]),
+];
+
+// each character is drawn in a 6x8 cell, leaving a bit of space between
+// characters
+const CELL_WIDTH: usize = 6;
+const CELL_HEIGHT: usize = 8;
+
+// line-art characters extend to the edges of their cells so walls and
+// hallways connect
+const LINES: &[u8] = b"-=+|";
+
+const PALETTE: &[[u8; 3]] = &[
+    [0x14, 0x14, 0x1c], // background
+    [0xc8, 0xc8, 0xc8], // walls/hallways/bubbles
+    [0xff, 0xa0, 0x40], // bubble centers
+    [0x40, 0xc0, 0xff], // lifts
+    [0x68, 0x68, 0x78], // undecided tiles
+    [0xff, 0x40, 0x40], // contradictions
+    [0xe0, 0xd0, 0x40], // engineering
+    [0x60, 0xd0, 0x60], // hydroponics
+    [0xb0, 0x80, 0x50], // cargo
+];
+
+// palette index for each character
+fn color(c: char) -> u8 {
+    match c {
+        ' '           => 0,
+        'o'           => 2,
+        '[' | ']'     => 3,
+        '0'..='9'     => 4,
+        '?'           => 4,
+        '!'           => 5,
+        '='           => 6,
+        '"'           => 7,
+        '#'           => 8,
+        _             => 1,
+    }
+}
+
+fn glyph(c: char) -> [u8; 7] {
+    FONT.iter()
+        .find(|(c_, _)| *c_ as char == c)
+        .map(|(_, glyph)| *glyph)
+        // unknown characters show up as a box
+        .unwrap_or([0x1f, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1f])
+}
+
+// rasterize lines of text into an indexed bitmap
+fn rasterize(frame: &[String], width: usize, height: usize) -> Vec<u8> {
+    let mut bitmap = vec![0; width*height];
+    for (y, line) in frame.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            if
+                c == ' '
+                    || (x+1)*CELL_WIDTH > width
+                    || (y+1)*CELL_HEIGHT > height
+            {
+                continue;
+            }
+
+            let mut set = |x_: usize, y_: usize| {
+                bitmap[
+                    (x*CELL_WIDTH+x_) + (y*CELL_HEIGHT+y_)*width
+                ] = color(c);
+            };
+
+            // box-drawing characters are drawn as lines from the center
+            // to each edge they connect to
+            if let Some([n, e, s, w]) = box_arms(c) {
+                let lower_x = if w { 0 } else { 2 };
+                let upper_x = if e { CELL_WIDTH-1 } else { 2 };
+                let lower_y = if n { 0 } else { 3 };
+                let upper_y = if s { CELL_HEIGHT-1 } else { 3 };
+                for x_ in lower_x ..= upper_x {
+                    set(x_, 3);
+                }
+                for y_ in lower_y ..= upper_y {
+                    set(2, y_);
+                }
+                continue;
+            }
+
+            let glyph = glyph(c);
+            let lines = c.is_ascii() && LINES.contains(&(c as u8));
+            for y_ in 0..CELL_HEIGHT {
+                // extend vertical lines into the gap between cells
+                let row = match y_ {
+                    7 if lines => glyph[6],
+                    7 => 0,
+                    _ => glyph[y_],
+                };
+                for x_ in 0..CELL_WIDTH {
+                    // extend horizontal lines into the gap between cells
+                    let bit = match x_ {
+                        5 => lines && row & 0x11 == 0x11,
+                        _ => row & (0x10 >> x_) != 0,
+                    };
+                    if bit {
+                        set(x_, y_);
+                    }
+                }
+            }
+        }
+    }
+    bitmap
+}
+
+// records frames as an animated gif
+//
+// like our asciinema recorder, we only know the final width/height once
+// we're done, so frames are buffered as text until then
+//
+#[derive(Debug)]
+pub struct GifRecorder {
+    file: File,
+    delay: u16,
+    skip: usize,
+    count: usize,
+    frames: Vec<Vec<String>>,
+    // most recent skipped frame, so we always end on the last frame
+    skipped: Option<Vec<String>>,
+}
+
+impl GifRecorder {
+    // delay is in seconds, and we only keep every skip'th frame
+    pub fn new(path: &Path, delay: f64, skip: usize) -> io::Result<Self> {
+        Ok(Self{
+            file: File::create(path)?,
+            // gifs measure delays in centiseconds
+            delay: (delay*100.0).round().clamp(1.0, u16::MAX as f64) as u16,
+            skip: cmp::max(skip, 1),
+            count: 0,
+            frames: vec![],
+            skipped: None,
+        })
+    }
+
+    pub fn frame(&mut self, frame: &[u8]) {
+        let frame = str::from_utf8(frame).unwrap()
+            .lines()
+            .map(|line| line.to_owned())
+            .collect::<Vec<_>>();
+
+        if self.count.is_multiple_of(self.skip) {
+            self.frames.push(frame);
+            self.skipped = None;
+        } else {
+            self.skipped = Some(frame);
+        }
+        self.count += 1;
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(frame) = self.skipped.take() {
+            self.frames.push(frame);
+        }
+
+        // gifs are limited to 16-bit dimensions
+        let cols = self.frames.iter()
+            .flat_map(|frame| frame.iter().map(|line| line.chars().count()))
+            .max()
+            .unwrap_or(0);
+        let rows = self.frames.iter()
+            .map(|frame| frame.len())
+            .max()
+            .unwrap_or(0);
+        let width = (cols*CELL_WIDTH).clamp(1, u16::MAX as usize);
+        let height = (rows*CELL_HEIGHT).clamp(1, u16::MAX as usize);
+
+        let palette = PALETTE.iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(self.file),
+            width as u16,
+            height as u16,
+            &palette,
+        ).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+
+        // only encode the region that changed in each frame, merging
+        // frames that don't change anything
+        let mut previous = vec![u8::MAX; width*height];
+        let mut pending: Option<gif::Frame> = None;
+        for frame in &self.frames {
+            let bitmap = rasterize(frame, width, height);
+
+            let mut lower = (width, height);
+            let mut upper = (0, 0);
+            for y in 0..height {
+                for x in 0..width {
+                    if bitmap[x+y*width] != previous[x+y*width] {
+                        lower = (cmp::min(lower.0, x), cmp::min(lower.1, y));
+                        upper = (
+                            cmp::max(upper.0, x+1),
+                            cmp::max(upper.1, y+1),
+                        );
+                    }
+                }
+            }
+
+            if lower.0 >= upper.0 {
+                if let Some(pending) = &mut pending {
+                    pending.delay = pending.delay.saturating_add(self.delay);
+                }
+                continue;
+            }
+
+            if let Some(pending) = pending.take() {
+                encoder.write_frame(&pending).map_err(io::Error::other)?;
+            }
+
+            let mut buffer = Vec::with_capacity(
+                (upper.0-lower.0)*(upper.1-lower.1)
+            );
+            for y in lower.1..upper.1 {
+                buffer.extend_from_slice(
+                    &bitmap[lower.0+y*width .. upper.0+y*width]
+                );
+            }
+            pending = Some(gif::Frame{
+                delay: self.delay,
+                dispose: gif::DisposalMethod::Keep,
+                left: lower.0 as u16,
+                top: lower.1 as u16,
+                width: (upper.0-lower.0) as u16,
+                height: (upper.1-lower.1) as u16,
+                buffer: Cow::Owned(buffer),
+                ..Default::default()
+            });
+            previous = bitmap;
+        }
+
+        // hold the last frame a bit longer
+        if let Some(mut pending) = pending {
+            pending.delay = pending.delay.saturating_add(300);
+            encoder.write_frame(&pending).map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+}
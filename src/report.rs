@@ -0,0 +1,238 @@
+use serde::Serialize;
+
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::*;
+
+
+// stats for a single pass of wfc, these happen once per chunk plus once
+// for any edits
+#[derive(Debug, Clone, Serialize)]
+pub struct WfcChunk {
+    // size of the window in tiles
+    pub width: usize,
+    pub height: usize,
+    // tiles we actually needed to resolve
+    pub unresolved: usize,
+    pub attempts: u64,
+    pub cycles: u64,
+    pub propagations: u64,
+    pub repairs: u64,
+    #[serde(serialize_with="serialize_secs")]
+    pub time: Duration,
+    pub success: bool,
+    // did we run out of budget, how many tiles did our fallback fill, and
+    // how many neighbors still break our constraints?
+    pub exhausted: bool,
+    pub fallback: usize,
+    pub breaches: usize,
+}
+
+// stats for a single deck
+#[derive(Debug, Clone, Serialize)]
+pub struct DeckReport {
+    pub seed: u64,
+    pub size: usize,
+    pub width: usize,
+    pub height: usize,
+    pub bubbles: usize,
+    // number of bubbles of each radius, in cells
+    pub radii: BTreeMap<usize, usize>,
+    // deepest bubble in the parent tree, the root is at depth 0
+    pub depth: usize,
+    pub hallways: usize,
+    // total length of all hallways, in cells
+    pub hallway_length: usize,
+    // hallways that aren't part of the tree, forming cycles
+    pub loops: usize,
+    // bubbles with only one hallway
+    pub dead_ends: usize,
+    pub bubble_cycles: u64,
+    #[serde(serialize_with="serialize_secs")]
+    pub bubble_time: Duration,
+    // size of the tile map in tiles
+    pub tile_width: usize,
+    pub tile_height: usize,
+    // number of each resolved tile by name
+    pub tiles: BTreeMap<&'static str, usize>,
+    pub doors: usize,
+    // connected regions of floor, separated by walls and doors
+    pub rooms: usize,
+    pub wfc_attempts: u64,
+    pub wfc_cycles: u64,
+    pub wfc_propagations: u64,
+    pub wfc_repairs: u64,
+    #[serde(serialize_with="serialize_secs")]
+    pub wfc_time: Duration,
+    pub wfc_exhausted: bool,
+    pub wfc_fallbacks: usize,
+    pub wfc_breaches: usize,
+    pub wfc_chunks: Vec<WfcChunk>,
+    // bubbles re-solved to meet their targets, and bubbles that still miss
+    pub target_rerolled: usize,
+    pub target_missed: usize,
+}
+
+// stats for a whole generation
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationReport {
+    pub seed: u64,
+    pub success: bool,
+    pub decks: Vec<DeckReport>,
+    pub lifts: usize,
+    // requested points we couldn't grow toward, as deck and point
+    pub unreachable: Vec<(usize, (isize, isize))>,
+    #[serde(serialize_with="serialize_secs")]
+    pub time: Duration,
+}
+
+// durations are written as fractional seconds
+fn serialize_secs<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl WaveStation {
+    pub fn report(&self) -> DeckReport {
+        let mut radii = BTreeMap::new();
+        for bubble in &self.bubbles {
+            *radii.entry(bubble.borrow().r).or_insert(0) += 1;
+        }
+
+        let hallway_length = self.hallways.iter()
+            .map(|(a, b)| {
+                let a = a.borrow();
+                let b = b.borrow();
+                (a.x - b.x).unsigned_abs() + (a.y - b.y).unsigned_abs()
+            })
+            .sum();
+
+        // count resolved tiles in our tile map
+        let mut tiles = BTreeMap::new();
+        let mut doors = 0;
+        let (cmap, _) = self.tiles.read(
+            self.cx, self.cy,
+            self.cwidth, self.cheight,
+        );
+        for c in cmap {
+            if c.count_ones() == 1 {
+                let i = 128-1-c.leading_zeros() as usize;
+                *tiles.entry(TILES[i].name).or_insert(0) += 1;
+                if c & TILE_DOORISH != 0 {
+                    doors += 1;
+                }
+            }
+        }
+
+        // count how many hallways each bubble has
+        let mut degrees = HashMap::new();
+        for (a, b) in &self.hallways {
+            *degrees.entry(Rc::as_ptr(a)).or_insert(0) += 1;
+            *degrees.entry(Rc::as_ptr(b)).or_insert(0) += 1;
+        }
+
+        DeckReport{
+            seed: self.seed,
+            size: self.size,
+            width: self.width,
+            height: self.height,
+            bubbles: self.bubbles.len(),
+            radii,
+            depth: self.bubble_depths().values().copied().max().unwrap_or(0),
+            hallways: self.hallways.len(),
+            hallway_length,
+            loops: (self.hallways.len()+1).saturating_sub(self.bubbles.len()),
+            dead_ends: degrees.values().filter(|&&d| d == 1).count(),
+            bubble_cycles: self.bubble_cycles,
+            bubble_time: self.bubble_time,
+            tile_width: self.cwidth,
+            tile_height: self.cheight,
+            tiles,
+            doors,
+            rooms: count_rooms(
+                &self.tiles.read(
+                    self.cx, self.cy,
+                    self.cwidth, self.cheight,
+                ).0,
+                self.cwidth, self.cheight,
+                None,
+            ),
+            wfc_attempts: self.wfc_chunks.iter()
+                .map(|chunk| chunk.attempts)
+                .sum(),
+            wfc_cycles: self.wfc_cycles,
+            wfc_propagations: self.wfc_propagations,
+            wfc_repairs: self.wfc_repairs,
+            wfc_time: self.wfc_time,
+            wfc_exhausted: self.wfc_exhausted,
+            wfc_fallbacks: self.wfc_fallbacks,
+            wfc_breaches: self.wfc_breaches,
+            wfc_chunks: self.wfc_chunks.clone(),
+            target_rerolled: self.target_rerolled,
+            target_missed: self.target_missed(),
+        }
+    }
+}
+
+// count connected regions of resolved floor in a constraint map with a
+// flood fill, optionally only counting floor inside a mask
+pub fn count_rooms(
+    cmap: &[u128],
+    width: usize,
+    height: usize,
+    inside: Option<&[bool]>,
+) -> usize {
+    let floorish = |i: usize| {
+        inside.is_none_or(|inside| inside[i])
+            && cmap[i].count_ones() == 1
+            && cmap[i] & TILE_FLOORISH != 0
+    };
+
+    let mut seen = vec![false; cmap.len()];
+    let mut rooms = 0;
+    let mut stack = vec![];
+    for i in 0..cmap.len() {
+        if seen[i] || !floorish(i) {
+            continue;
+        }
+
+        rooms += 1;
+        seen[i] = true;
+        stack.push(i);
+        while let Some(j) = stack.pop() {
+            let (x, y) = (j % width, j / width);
+            let neighbors = [
+                (y > 0).then(|| j-width),
+                (x+1 < width).then(|| j+1),
+                (y+1 < height).then(|| j+width),
+                (x > 0).then(|| j-1),
+            ];
+            for k in neighbors.into_iter().flatten() {
+                if !seen[k] && floorish(k) {
+                    seen[k] = true;
+                    stack.push(k);
+                }
+            }
+        }
+    }
+    rooms
+}
+
+impl GenerationReport {
+    pub fn new(generation: &Generation) -> Self {
+        Self{
+            seed: generation.seed,
+            success: generation.success,
+            decks: generation.decks.decks.iter()
+                .map(|ws| ws.report())
+                .collect(),
+            lifts: generation.decks.lifts.len(),
+            unreachable: generation.unreachable.clone(),
+            time: generation.time,
+        }
+    }
+}
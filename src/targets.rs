@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::*;
+
+
+// a target number of rooms/doors for bubbles of a given size
+//
+// the inner wall tiles only enforce "one door for each room" locally, so
+// these are checked after wfc, re-solving any bubbles that miss
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BubbleTarget {
+    // applies to bubbles at least this big, in cells
+    pub radius: usize,
+    pub min_rooms: usize,
+    pub max_rooms: Option<usize>,
+    pub doors: Option<usize>,
+}
+
+impl FromStr for BubbleTarget {
+    type Err = String;
+
+    // parse radius:rooms[:doors], where rooms is either n, min-max, or
+    // min- for no max
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').map(|p| p.trim()).collect::<Vec<_>>();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(format!("expected radius:rooms[:doors], found {:?}",
+                s
+            ));
+        }
+
+        let radius = parse_usize(parts[0]).map_err(|e| e.to_string())?;
+        let (min_rooms, max_rooms) = match parts[1].split_once('-') {
+            Some((min, "")) => (
+                parse_usize(min).map_err(|e| e.to_string())?,
+                None,
+            ),
+            Some((min, max)) => (
+                parse_usize(min).map_err(|e| e.to_string())?,
+                Some(parse_usize(max).map_err(|e| e.to_string())?),
+            ),
+            None => {
+                let rooms = parse_usize(parts[1]).map_err(|e| e.to_string())?;
+                (rooms, Some(rooms))
+            }
+        };
+        if max_rooms.is_some_and(|max_rooms| max_rooms < min_rooms) {
+            return Err(format!("empty room range {:?}", parts[1]));
+        }
+        let doors = match parts.get(2) {
+            Some(doors) => Some(
+                parse_usize(doors).map_err(|e| e.to_string())?
+            ),
+            None => None,
+        };
+
+        Ok(BubbleTarget{
+            radius,
+            min_rooms,
+            max_rooms,
+            doors,
+        })
+    }
+}
+
+impl BubbleTarget {
+    // find the target for a bubble, this is the target with the largest
+    // radius that still fits the bubble
+    pub fn find(targets: &[BubbleTarget], r: usize) -> Option<&BubbleTarget> {
+        targets.iter()
+            .filter(|target| target.radius <= r)
+            .max_by_key(|target| target.radius)
+    }
+
+    pub fn met(&self, rooms: usize, doors: usize) -> bool {
+        rooms >= self.min_rooms
+            && self.max_rooms.is_none_or(|max_rooms| rooms <= max_rooms)
+            && self.doors.is_none_or(|doors_| doors == doors_)
+    }
+}
+
+impl WaveStation {
+    // count the rooms and doors inside a bubble
+    //
+    // rooms are connected regions of resolved floor inside the bubble,
+    // any hallways leading in just join whatever room they reach
+    pub fn bubble_rooms(&self, bubble: &Rc<RefCell<Bubble>>) -> (usize, usize) {
+        let x = bubble.borrow().x * self.scale as isize;
+        let y = bubble.borrow().y * self.scale as isize;
+        let r = bubble.borrow().r * self.scale;
+        let width = 2*r+1;
+        let (cmap, _) = self.tiles.read(
+            x - r as isize, y - r as isize,
+            width, width,
+        );
+        let inside = (0..cmap.len())
+            .map(|i| {
+                distsq(((i % width) as isize, (i / width) as isize),
+                    (r as isize, r as isize)) <= sq(r)
+            })
+            .collect::<Vec<_>>();
+
+        let doors = (0..cmap.len())
+            .filter(|&i| {
+                inside[i]
+                    && cmap[i].count_ones() == 1
+                    && cmap[i] & TILE_DOORISH != 0
+            })
+            .count();
+
+        (count_rooms(&cmap, width, width, Some(&inside)), doors)
+    }
+
+    // check the targets of any bubbles our last wfc pass could have
+    // changed, returning any that miss
+    //
+    // note only bubbles that overlap our window can have changed, so
+    // this doesn't grow with the size of our station
+    fn check_targets(&self) -> Vec<Rc<RefCell<Bubble>>> {
+        let scale = self.scale as isize;
+        self.bubbles.iter()
+            .filter(|bubble| {
+                let x = bubble.borrow().x * scale;
+                let y = bubble.borrow().y * scale;
+                let r = (bubble.borrow().r * self.scale) as isize;
+                x+r >= self.wx && x-r < self.wx + self.wwidth as isize
+                    && y+r >= self.wy && y-r < self.wy + self.wheight as isize
+            })
+            .filter(|bubble| {
+                let missed = match BubbleTarget::find(
+                    &self.targets,
+                    bubble.borrow().r,
+                ) {
+                    Some(target) => {
+                        let (rooms, doors) = self.bubble_rooms(bubble);
+                        !target.met(rooms, doors)
+                    }
+                    None => false,
+                };
+                bubble.borrow_mut().target_missed = missed;
+                missed
+            })
+            .cloned()
+            .collect()
+    }
+
+    // number of bubbles that missed their targets when last checked
+    pub fn target_missed(&self) -> usize {
+        self.bubbles.iter()
+            .filter(|bubble| bubble.borrow().target_missed)
+            .count()
+    }
+
+    // re-solve any bubbles that miss their targets, up to target_rerolls
+    // times per bubble, note frozen bubbles are left as they are
+    //
+    // returns false if wfc fails, missing targets is not a failure
+    pub fn meet_targets(
+        &mut self,
+        mut anim_term: Option<&mut BackgroundTerminal>,
+        anim_sleep: Option<Duration>,
+    ) -> bool {
+        if self.targets.is_empty() || self.tiles.is_empty() {
+            return true;
+        }
+
+        let mut missed = self.check_targets();
+        loop {
+            missed.retain(|bubble| {
+                !bubble.borrow().frozen
+                    && bubble.borrow().target_tries < self.target_rerolls
+            });
+            if missed.is_empty() {
+                break;
+            }
+
+            for bubble in &missed {
+                bubble.borrow_mut().target_tries += 1;
+                self.reroll_bubble(bubble);
+            }
+            self.target_rerolled += missed.len();
+            if !self.wfc(anim_term.as_deref_mut(), anim_sleep) {
+                return false;
+            }
+            missed = self.check_targets();
+        }
+
+        true
+    }
+}
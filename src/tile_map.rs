@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::cmp;
+
+use crate::constraints::*;
+use crate::zones::*;
+
+
+// width/height of each page in tiles
+const PAGE_SIZE: usize = 32;
+
+#[derive(Debug, Clone)]
+struct Page {
+    cmap: Vec<u128>,
+    zmap: Vec<Option<Zone>>,
+}
+
+impl Page {
+    fn new() -> Page {
+        Page{
+            cmap: vec![TILE_SPACE; PAGE_SIZE*PAGE_SIZE],
+            zmap: vec![None; PAGE_SIZE*PAGE_SIZE],
+        }
+    }
+}
+
+// a sparse constraint map, stored in fixed-size pages
+//
+// coordinates here are signed tiles with the root bubble's center at 0,0,
+// so they never change as the station grows, missing pages are all space
+//
+#[derive(Debug, Clone, Default)]
+pub struct TileMap {
+    pages: HashMap<(isize, isize), Page>,
+}
+
+impl TileMap {
+    pub fn new() -> TileMap {
+        TileMap{pages: HashMap::new()}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    fn page_of(x: isize, y: isize) -> ((isize, isize), usize) {
+        let p_x = x.div_euclid(PAGE_SIZE as isize);
+        let p_y = y.div_euclid(PAGE_SIZE as isize);
+        let i = x.rem_euclid(PAGE_SIZE as isize) as usize
+            + y.rem_euclid(PAGE_SIZE as isize) as usize*PAGE_SIZE;
+        ((p_x, p_y), i)
+    }
+
+    pub fn get(&self, x: isize, y: isize) -> u128 {
+        let (p, i) = Self::page_of(x, y);
+        self.pages.get(&p).map_or(TILE_SPACE, |page| page.cmap[i])
+    }
+
+    pub fn zone(&self, x: isize, y: isize) -> Option<Zone> {
+        let (p, i) = Self::page_of(x, y);
+        self.pages.get(&p).and_then(|page| page.zmap[i])
+    }
+
+    pub fn set(&mut self, x: isize, y: isize, c: u128, zone: Option<Zone>) {
+        let (p, i) = Self::page_of(x, y);
+        // don't bother allocating pages for space
+        if c == TILE_SPACE && zone.is_none() && !self.pages.contains_key(&p) {
+            return;
+        }
+        let page = self.pages.entry(p).or_insert_with(Page::new);
+        page.cmap[i] = c;
+        page.zmap[i] = zone;
+    }
+
+    // iterate over the pages overlapping a window, providing the page
+    // coordinates and the overlapping range in tiles
+    fn overlapping(
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+    ) -> impl Iterator<
+        Item=((isize, isize), (isize, isize), (isize, isize))
+    > {
+        let size = PAGE_SIZE as isize;
+        let lower_x = x.div_euclid(size);
+        let lower_y = y.div_euclid(size);
+        let upper_x = (x + width as isize - 1).div_euclid(size);
+        let upper_y = (y + height as isize - 1).div_euclid(size);
+        (lower_y ..= upper_y)
+            .flat_map(move |p_y| {
+                (lower_x ..= upper_x).map(move |p_x| (p_x, p_y))
+            })
+            .filter(move |_| width > 0 && height > 0)
+            .map(move |(p_x, p_y)| {
+                (
+                    (p_x, p_y),
+                    (
+                        cmp::max(x, p_x*size),
+                        cmp::min(x + width as isize, (p_x+1)*size),
+                    ),
+                    (
+                        cmp::max(y, p_y*size),
+                        cmp::min(y + height as isize, (p_y+1)*size),
+                    ),
+                )
+            })
+    }
+
+    // copy a window out into a dense constraint map and zone map
+    pub fn read(
+        &self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+    ) -> (Vec<u128>, Vec<Option<Zone>>) {
+        let mut cmap = vec![TILE_SPACE; width*height];
+        let mut zmap = vec![None; width*height];
+        for (p, (lower_x, upper_x), (lower_y, upper_y))
+            in Self::overlapping(x, y, width, height)
+        {
+            let page = match self.pages.get(&p) {
+                Some(page) => page,
+                None => continue,
+            };
+
+            let len = (upper_x - lower_x) as usize;
+            for y_ in lower_y..upper_y {
+                let (_, i) = Self::page_of(lower_x, y_);
+                let j = (lower_x-x) as usize + (y_-y) as usize*width;
+                cmap[j..j+len].copy_from_slice(&page.cmap[i..i+len]);
+                zmap[j..j+len].copy_from_slice(&page.zmap[i..i+len]);
+            }
+        }
+
+        (cmap, zmap)
+    }
+
+    // copy a dense constraint map and zone map back into our pages
+    pub fn write(
+        &mut self,
+        x: isize,
+        y: isize,
+        width: usize,
+        height: usize,
+        cmap: &[u128],
+        zmap: &[Option<Zone>],
+    ) {
+        debug_assert!(cmap.len() == width*height);
+        debug_assert!(zmap.len() == width*height);
+        for (p, (lower_x, upper_x), (lower_y, upper_y))
+            in Self::overlapping(x, y, width, height)
+        {
+            let len = (upper_x - lower_x) as usize;
+            let rows = || (lower_y..upper_y).map(|y_| {
+                let (_, i) = Self::page_of(lower_x, y_);
+                let j = (lower_x-x) as usize + (y_-y) as usize*width;
+                (i, j)
+            });
+
+            // don't bother allocating pages for space
+            if
+                !self.pages.contains_key(&p)
+                    && rows().all(|(_, j)| {
+                        cmap[j..j+len].iter().all(|&c| c == TILE_SPACE)
+                            && zmap[j..j+len].iter().all(|z| z.is_none())
+                    })
+            {
+                continue;
+            }
+
+            let page = self.pages.entry(p).or_insert_with(Page::new);
+            for (i, j) in rows() {
+                page.cmap[i..i+len].copy_from_slice(&cmap[j..j+len]);
+                page.zmap[i..i+len].copy_from_slice(&zmap[j..j+len]);
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::{self, Read, Write};
+use std::mem;
+use std::env;
+use std::str::FromStr;
+
+use crate::*;
+
+
+// put the terminal into raw mode, restoring it when dropped
+struct RawTerminal {
+    termios: libc::termios,
+}
+
+impl RawTerminal {
+    fn new() -> io::Result<Self> {
+        let termios = unsafe {
+            let mut termios = mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = termios;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios
+        };
+
+        // \x1b[?1049h => switch to alternate screen
+        // \x1b[?25l   => hide cursor
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+
+        Ok(Self{termios})
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // \x1b[?25h   => show cursor
+        // \x1b[?1049l => switch back to main screen
+        print!("\x1b[?25h\x1b[?1049l");
+        io::stdout().flush().unwrap();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.termios);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Char(u8),
+    Resize,
+}
+
+// read a key from raw stdin, skipping anything we don't understand
+fn read_key(stdin: &mut io::Stdin) -> io::Result<Key> {
+    loop {
+        let mut buf = [0; 8];
+        let n = match stdin.read(&mut buf) {
+            Ok(n) => n,
+            // interrupted by SIGWINCH?
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                if resized() {
+                    return Ok(Key::Resize);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        match &buf[..n] {
+            [] => return Ok(Key::Char(b'q')),
+            b"\x1b[A" => return Ok(Key::Up),
+            b"\x1b[B" => return Ok(Key::Down),
+            b"\x1b[C" => return Ok(Key::Right),
+            b"\x1b[D" => return Ok(Key::Left),
+            [c] => return Ok(Key::Char(*c)),
+            _ => {}
+        }
+    }
+}
+
+// size of the terminal, falling back to $COLUMNS/$LINES or 80x24
+fn view_size() -> (usize, usize) {
+    terminal_size().unwrap_or_else(|| {
+        let var = |name, default| {
+            env::var(name).ok()
+                .and_then(|v| usize::from_str(&v).ok())
+                .unwrap_or(default)
+        };
+        (var("COLUMNS", 80), var("LINES", 24))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Map {
+    Small,
+    Bubble,
+    Tile,
+}
+
+impl Map {
+    fn name(self) -> &'static str {
+        match self {
+            Map::Small  => "small map",
+            Map::Bubble => "bubble map",
+            Map::Tile   => "tile map",
+        }
+    }
+
+    fn next(self) -> Map {
+        match self {
+            Map::Small  => Map::Bubble,
+            Map::Bubble => Map::Tile,
+            Map::Tile   => Map::Small,
+        }
+    }
+}
+
+// an interactive viewer for exploring generated stations
+pub struct Viewer {
+    decks: Decks,
+    deck: usize,
+    map: Map,
+    pan_x: isize,
+    pan_y: isize,
+    small_width: usize,
+    small_height: usize,
+    bubble: Option<usize>,
+}
+
+impl Viewer {
+    pub fn new(
+        decks: Decks,
+        small_width: usize,
+        small_height: usize,
+    ) -> Self {
+        Self{
+            decks,
+            deck: 0,
+            map: Map::Bubble,
+            pan_x: 0,
+            pan_y: 0,
+            small_width,
+            small_height,
+            bubble: None,
+        }
+    }
+
+    // render the current map with the existing render functions, as
+    // rows of chars
+    fn render(&self) -> (usize, usize, Vec<char>) {
+        let ws = &self.decks.decks[self.deck];
+        match self.map {
+            Map::Small => ws.render_small_map(
+                self.small_width,
+                self.small_height,
+            ),
+            Map::Bubble => ws.render_bubble_map(),
+            Map::Tile => ws.render_tile_map(),
+        }
+    }
+
+    // where is a bubble in the current map?
+    fn locate(&self, i: usize) -> (isize, isize) {
+        let ws = &self.decks.decks[self.deck];
+        let bubble = ws.bubbles[i].borrow();
+        let x = bubble.x - ws.lower_x;
+        let y = bubble.y - ws.lower_y;
+        match self.map {
+            Map::Small => (
+                (x as f64 * self.small_width as f64 / ws.width as f64)
+                    as isize,
+                (y as f64 * self.small_height as f64 / ws.height as f64)
+                    as isize,
+            ),
+            Map::Bubble => (x, y),
+            // tiles are two chars wide
+            Map::Tile => (
+                x * ws.scale as isize * 2,
+                y * ws.scale as isize,
+            ),
+        }
+    }
+
+    fn draw(&mut self, status: &str) -> io::Result<()> {
+        let (cols, rows) = view_size();
+        // leave a line for our status
+        let rows = rows.saturating_sub(1);
+        let (width, height, map) = self.render();
+
+        // center on our bubble if we have one
+        if let Some(i) = self.bubble {
+            let (x, y) = self.locate(i);
+            self.pan_x = x - cols as isize/2;
+            self.pan_y = y - rows as isize/2;
+        }
+
+        // keep our view on the map
+        self.pan_x = self.pan_x
+            .min(width as isize - cols as isize)
+            .max(0);
+        self.pan_y = self.pan_y
+            .min(height as isize - rows as isize)
+            .max(0);
+
+        let mut out = Vec::new();
+        // \x1b[H => move cursor home
+        write!(out, "\x1b[H")?;
+        for y in 0..rows {
+            let y_ = self.pan_y as usize + y;
+            if y_ < height {
+                let lower = cmp::min(self.pan_x as usize, width);
+                let upper = cmp::min(lower + cols, width);
+                out.extend(
+                    map[y_*width+lower .. y_*width+upper].iter()
+                        .collect::<String>()
+                        .bytes()
+                );
+            }
+            // \x1b[K => clear line
+            write!(out, "\x1b[K\r\n")?;
+        }
+
+        // show our status, inverted
+        let ws = &self.decks.decks[self.deck];
+        let mut line = format!(
+            "{}{}{} | {},{} | seed 0x{:016x} | {}",
+            if self.decks.decks.len() > 1 {
+                format!("deck {}/{} | ", self.deck, self.decks.decks.len())
+            } else {
+                String::new()
+            },
+            self.map.name(),
+            self.bubble
+                .map_or(String::new(), |i| format!(" | bubble {}", i)),
+            self.pan_x, self.pan_y,
+            ws.seed,
+            status,
+        );
+        line.truncate(cols);
+        // \x1b[7m => inverse
+        // \x1b[0m => reset
+        write!(out, "\x1b[7m{}\x1b[K\x1b[0m", line)?;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(&out)?;
+        stdout.flush()
+    }
+
+    // run the viewer until the user quits, regen is used to re-roll the
+    // station with a new seed
+    pub fn run<F>(&mut self, mut regen: F) -> io::Result<()>
+    where
+        F: FnMut() -> io::Result<Decks>
+    {
+        const HELP: &str = "arrows/hjkl: pan, m: map, +/-: zoom, \
+            n/p: bubble, d: deck, r: reroll, q: quit";
+
+        let _raw = RawTerminal::new()?;
+        watch_resize();
+        let mut stdin = io::stdin();
+        let mut status = HELP;
+        loop {
+            self.draw(status)?;
+            status = HELP;
+
+            let (cols, rows) = view_size();
+            let step_x = cmp::max(cols/4, 1) as isize;
+            let step_y = cmp::max(rows/4, 1) as isize;
+            let key = read_key(&mut stdin)?;
+            match key {
+                Key::Left  | Key::Char(b'h') => {
+                    self.bubble = None;
+                    self.pan_x -= step_x;
+                }
+                Key::Right | Key::Char(b'l') => {
+                    self.bubble = None;
+                    self.pan_x += step_x;
+                }
+                Key::Up    | Key::Char(b'k') => {
+                    self.bubble = None;
+                    self.pan_y -= step_y;
+                }
+                Key::Down  | Key::Char(b'j') => {
+                    self.bubble = None;
+                    self.pan_y += step_y;
+                }
+                Key::Char(b'm') | Key::Char(b'\t') => {
+                    self.map = self.map.next();
+                }
+                Key::Char(b'+') | Key::Char(b'=') => {
+                    self.map = Map::Small;
+                    self.small_width = cmp::min(self.small_width*2, 4096);
+                    self.small_height = cmp::min(self.small_height*2, 4096);
+                }
+                Key::Char(b'-') => {
+                    self.map = Map::Small;
+                    self.small_width = cmp::max(self.small_width/2, 1);
+                    self.small_height = cmp::max(self.small_height/2, 1);
+                }
+                Key::Char(b'n') | Key::Char(b'p') => {
+                    let count = self.decks.decks[self.deck].bubbles.len();
+                    self.bubble = Some(match (self.bubble, key) {
+                        (None, _) => 0,
+                        (Some(i), Key::Char(b'n')) => (i+1) % count,
+                        (Some(i), _) => (i+count-1) % count,
+                    });
+                }
+                Key::Char(b'd') => {
+                    self.deck = (self.deck+1) % self.decks.decks.len();
+                    self.bubble = None;
+                }
+                Key::Char(b'r') => {
+                    self.draw("generating...")?;
+                    self.decks = regen()?;
+                    self.deck = 0;
+                    self.bubble = None;
+                }
+                Key::Resize => {
+                    // \x1b[2J => clear screen
+                    print!("\x1b[2J");
+                }
+                Key::Char(b'q') | Key::Char(b'\x1b') | Key::Char(3) => {
+                    return Ok(());
+                }
+                _ => {
+                    status = "unknown key";
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1287 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, hash_map, btree_map};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use crate::*;
+
+
+// keep track of unresolved constraints
+//
+// this requires a bit of a unique data structure, we need to be
+// able to:
+// 1. insert unique constraints
+// 2. remove unique constraints
+// 3. choose a random constraint with the lowest amount of
+//    possiblities remaining
+//
+// unfortunately since we want this to be deterministic and random
+// we can't just choose an arbitrary item from a BTreeMap/HashMap
+//
+#[derive(Debug, Clone)]
+pub struct ConstraintSet {
+    buckets: BTreeMap<u32, Bucket>,
+}
+
+// each bucket maps constraints to their index in a vec, so we can both
+// remove and choose constraints quickly
+type Bucket = (HashMap<(usize, usize), usize>, Vec<(usize, usize)>);
+
+impl ConstraintSet {
+    pub fn new() -> ConstraintSet {
+        ConstraintSet{buckets: BTreeMap::new()}
+    }
+
+    pub fn insert(&mut self, c: u32, x: usize, y: usize) -> bool {
+        let (ref mut map, ref mut bucket) = self.buckets.entry(c)
+            .or_insert_with(|| (HashMap::new(), Vec::new()));
+        match map.entry((x, y)) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(e) => {
+                let i = bucket.len();
+                bucket.push((x, y));
+                e.insert(i);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, c: u32, x: usize, y: usize) -> bool {
+        match self.buckets.entry(c) {
+            btree_map::Entry::Occupied(mut e) => {
+                let (ref mut map, ref mut bucket) = e.get_mut();
+                match map.entry((x, y)) {
+                    // one entry? drop bucket
+                    hash_map::Entry::Occupied(_) if bucket.len() <= 1 => {
+                        e.remove();
+                        true
+                    }
+                    // more entries? need to swap-remove, this gets
+                    // a bit messy since we also need to update the
+                    // swapped entry's map entry
+                    hash_map::Entry::Occupied(e) => {
+                        let i = e.remove();
+                        let (x_, y_) = bucket.pop().unwrap();
+                        if i < bucket.len() {
+                            bucket[i] = (x_, y_);
+                            map.insert((x_, y_), i);
+                        }
+                        true
+                    }
+                    hash_map::Entry::Vacant(_) => false,
+                }
+            },
+            btree_map::Entry::Vacant(_) => false,
+        }
+    }
+
+    pub fn pop(
+        &mut self,
+        prng: &mut Xorshift64,
+    ) -> Option<(u32, usize, usize)> {
+        match self.buckets.first_key_value() {
+            Some((&c, (_, ref bucket))) => {
+                // in case of tie, choose randomly
+                let (x, y) = bucket[prng.range(0..bucket.len())];
+                self.remove(c, x, y);
+                Some((c, x, y))
+            }
+            None => None,
+        }
+    }
+}
+
+// called with our constraint map after each propagation, this is really
+// just for animating
+pub type Anim<'a> = Option<&'a mut dyn FnMut(&[u128])>;
+
+// a log of changes to a constraint map, this lets us roll back failed
+// attempts, or to any earlier checkpoint, without copying the whole map
+//
+// note overlap also uses this for its pattern domains, which are u64s
+#[derive(Debug, Clone, Default)]
+pub struct Trail<T=u128> {
+    changes: Vec<(usize, T)>,
+}
+
+impl<T: Copy> Trail<T> {
+    pub fn new() -> Trail<T> {
+        Trail{changes: Vec::new()}
+    }
+
+    // update a cell, remembering its old value
+    pub fn set(&mut self, cmap: &mut [T], i: usize, c: T) {
+        self.changes.push((i, cmap[i]));
+        cmap[i] = c;
+    }
+
+    // a point we can roll back to later
+    pub fn checkpoint(&self) -> usize {
+        self.changes.len()
+    }
+
+    // undo any changes made since a checkpoint
+    pub fn rollback(&mut self, cmap: &mut [T], checkpoint: usize) {
+        while self.changes.len() > checkpoint {
+            let (i, c) = self.changes.pop().unwrap();
+            cmap[i] = c;
+        }
+    }
+}
+
+// stats for a wfc solve
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WfcStats {
+    pub attempts: u64,
+    pub cycles: u64,
+    pub propagations: u64,
+    pub repairs: u64,
+    // did we run out of budget?
+    pub exhausted: bool,
+}
+
+// limits on how long wfc can run before giving up with a partial result
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    // wall-clock deadline
+    pub deadline: Option<Instant>,
+    // max propagations, counted by whoever is solving, note solve_regions
+    // splits this between its regions
+    pub propagations: Option<u64>,
+    // set from another thread to give up early
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Budget {
+    pub fn exceeded(&self, propagations: u64) -> bool {
+        self.propagations.is_some_and(|limit| propagations >= limit)
+            || self.deadline.is_some_and(|deadline| {
+                Instant::now() >= deadline
+            })
+            || self.cancel.as_ref().is_some_and(|cancel| {
+                cancel.load(Ordering::Relaxed)
+            })
+    }
+}
+
+// what to do with any unresolved tiles if we run out of budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    // give up, same as failing to resolve constraints
+    Error,
+    // resolve to floor or wall, whichever most neighbors are, treating
+    // space as wall
+    Majority,
+    // turn into space, with walls wherever space would touch our resolved
+    // neighbors, sealing off the unresolved region
+    Seal,
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error"    => Ok(Fallback::Error),
+            "majority" => Ok(Fallback::Majority),
+            "seal"     => Ok(Fallback::Seal),
+            _ => Err(format!("unknown fallback {:?}", s)),
+        }
+    }
+}
+
+impl Fallback {
+    // fill in any unresolved tiles, returning how many we filled, and how
+    // many neighbors still break our constraints
+    //
+    // we fill in reading order, propagating each tile to our unresolved
+    // neighbors as we go, so every tile fits whatever is already resolved
+    // around it and leaves its neighbors something to fit, unless nothing
+    // fits at all
+    pub fn fill(
+        self,
+        cmap: &mut [u128],
+        width: usize,
+        height: usize,
+    ) -> (usize, usize) {
+        if self == Fallback::Error {
+            return (0, 0);
+        }
+
+        let supports = tile_supports();
+        let walls = TILE_WALLISH | TILE_INWALLISH;
+        let unresolved = (0..width*height)
+            .filter(|&i| cmap[i].count_ones() != 1)
+            .collect::<Vec<_>>();
+
+        // find our neighbors, in the same order as DIRS
+        let neighbors = |i: usize| {
+            let (x, y) = (i % width, i / width);
+            [
+                (y > 0).then(|| i-width),
+                (x+1 < width).then(|| i+1),
+                (y+1 < height).then(|| i+width),
+                (x > 0).then(|| i-1),
+            ]
+        };
+
+        // keep track of what each unresolved tile can still be, note we
+        // start over from all tiles here, whatever wfc was in the middle of
+        // may already be a dead end
+        //
+        // like resolve_relaxed in our adjacency tool, propagating never
+        // leaves a tile nothing, if nothing fits we'll have to break our
+        // constraints somewhere
+        let mut domains = cmap.iter()
+            .map(|&c| if c.count_ones() == 1 { c } else { TILE_ALL })
+            .collect::<Vec<_>>();
+        let propagate = |domains: &mut [u128], mut pending: Vec<usize>| {
+            while let Some(i) = pending.pop() {
+                for (d, j) in neighbors(i).into_iter().enumerate() {
+                    let Some(j) = j else { continue };
+                    if domains[j].count_ones() <= 1 {
+                        continue;
+                    }
+
+                    let allowed = (0..TILES.len())
+                        .filter(|&t| domains[i] & (1 << t) != 0)
+                        .fold(0, |allowed, t| allowed | supports[t][d]);
+                    let c = domains[j] & allowed;
+                    if c != 0 && c != domains[j] {
+                        domains[j] = c;
+                        pending.push(j);
+                    }
+                }
+            }
+        };
+        propagate(&mut domains, (0..width*height).collect());
+
+        for &i in &unresolved {
+            let c = cmap[i];
+            let resolved = neighbors(i).into_iter()
+                .zip(DIRS)
+                .filter_map(|(j, dir)| {
+                    j.map(|j| (cmap[j], dir))
+                        .filter(|(c_, _)| c_.count_ones() == 1)
+                })
+                .collect::<Vec<_>>();
+
+            // choose the first tile in a class that fits our resolved
+            // neighbors, and is in our domain if we can
+            let fit = |class: u128, domain: u128| {
+                (0..TILES.len())
+                    .map(|t| 1 << t)
+                    .filter(|&t| class & domain & t != 0)
+                    .find(|&t| {
+                        resolved.iter().all(|&(c_, dir)| {
+                            compatible(t, c_, dir)
+                        })
+                    })
+            };
+            let choose = |domain: u128| {
+                let fit = |class: u128| fit(class, domain);
+                match self {
+                    Fallback::Error => unreachable!(),
+                    // space needs a wall between it and any floor, so wall
+                    // off anything space doesn't fit next to
+                    Fallback::Seal => {
+                        fit(TILE_SPACE)
+                            .or_else(|| fit(c & TILE_WALLISH))
+                            .or_else(|| fit(TILE_WALLISH))
+                            .or_else(|| fit(walls))
+                            .or_else(|| fit(TILE_ALL))
+                    }
+                    Fallback::Majority => {
+                        // space needs a wall between it and any floor
+                        let floors = resolved.iter()
+                            .filter(|(c_, _)| c_ & TILE_FLOORISH != 0)
+                            .count();
+                        let walls_ = resolved.iter()
+                            .filter(|(c_, _)| {
+                                c_ & (walls | TILE_SPACEISH) != 0
+                            })
+                            .count();
+                        let class = if walls_ > floors {
+                            walls
+                        } else {
+                            TILE_FLOOR
+                        };
+
+                        fit(c & class)
+                            .or_else(|| fit(class))
+                            .or_else(|| fit(TILE_ALL))
+                    }
+                }
+            };
+
+            // if nothing fits, we have no choice but to break our
+            // constraints
+            let t = choose(domains[i])
+                .or_else(|| choose(TILE_ALL))
+                .unwrap_or(match self {
+                    Fallback::Majority => TILE_FLOOR,
+                    _ => TILE_SPACE,
+                });
+            cmap[i] = t;
+            domains[i] = t;
+            propagate(&mut domains, vec![i]);
+        }
+
+        // re-check what we filled, this should only break our constraints
+        // if nothing fit at all
+        let breaches = unresolved.iter()
+            .map(|&i| {
+                neighbors(i).into_iter()
+                    .zip(DIRS)
+                    .filter(|&(j, dir)| {
+                        j.is_some_and(|j| !compatible(cmap[i], cmap[j], dir))
+                    })
+                    .count()
+            })
+            .sum();
+
+        (unresolved.len(), breaches)
+    }
+}
+
+// how we propagate constraints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagator {
+    // recompute a cell's constraints from all of its neighbors whenever
+    // one of them changes
+    Naive,
+    // keep per-cell, per-direction support counts for each tile, and only
+    // remove tiles whose support drops to zero, AC-4 style
+    Ac4,
+}
+
+impl FromStr for Propagator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "naive" => Ok(Propagator::Naive),
+            "ac4"   => Ok(Propagator::Ac4),
+            _ => Err(format!("unknown propagator {:?}", s)),
+        }
+    }
+}
+
+// directions in the order we store supports, note flipping a direction
+// is just adding 2
+const DIRS: [Dir; 4] = [Dir::N, Dir::E, Dir::S, Dir::W];
+
+// which tiles can tile t sit next to, in each direction? this checks both
+// tiles' constraints
+pub fn tile_supports() -> Vec<[u128; 4]> {
+    (0..TILES.len())
+        .map(|t| {
+            let mut supports = [0; 4];
+            for (d, &dir) in DIRS.iter().enumerate() {
+                for (t_, tile_) in TILES.iter().enumerate() {
+                    if
+                        TILES[t].constraints.dir(dir) & (1 << t_) != 0
+                            && tile_.constraints.dir(dir.flip())
+                                & (1 << t) != 0
+                    {
+                        supports[d] |= 1 << t_;
+                    }
+                }
+            }
+            supports
+        })
+        .collect()
+}
+
+// work waiting on our propagator
+struct Pending {
+    // cells to re-evaluate, for the naive propagator
+    cells: Vec<(usize, usize)>,
+    // removed tiles that still need to update their neighbors' supports,
+    // and support counts indexed by cell, direction, and tile, for AC-4
+    removals: Vec<(usize, usize)>,
+    supports: Vec<u8>,
+    compat: Vec<[u128; 4]>,
+    // cells whose supports need to be recounted, for AC-4
+    stale: Vec<(usize, usize)>,
+}
+
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.removals.is_empty()
+            && self.stale.is_empty()
+    }
+}
+
+// how did propagation go?
+pub enum Propagated {
+    Done,
+    Contradiction(usize, usize),
+    Exhausted,
+}
+
+// how far past anything that changed we copy out for wfc, in tiles, this
+// only needs to cover any fixed neighbors since resolved tiles never change
+pub const WFC_MARGIN: isize = 1;
+
+// how far around a conflicting seam we re-solve, in tiles
+const SEAM_MARGIN: usize = 3;
+
+// a dense window of constraints to resolve with wave-function collapse
+pub struct Wfc<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub zmap: &'a [Option<Zone>],
+    // if provided, only these cells may change, everything else is treated
+    // as fixed
+    pub live: Option<&'a [bool]>,
+    pub attempts: u64,
+    pub budget: &'a Budget,
+    // how many times we try to repair contradictions before restarting
+    pub repair: usize,
+    pub propagator: Propagator,
+}
+
+
+// can these two cells sit next to each other, with c_ in direction dir?
+fn compatible(c: u128, c_: u128, dir: Dir) -> bool {
+    (0..TILES.len())
+        .filter(|&i| c & (1 << i) != 0)
+        .all(|i| TILES[i].constraints.dir(dir) & c_ != 0)
+    && (0..TILES.len())
+        .filter(|&i| c_ & (1 << i) != 0)
+        .all(|i| TILES[i].constraints.dir(dir.flip()) & c != 0)
+}
+
+impl Wfc<'_> {
+    fn live(&self, x: usize, y: usize) -> bool {
+        self.live.is_none_or(|live| live[x+y*self.width])
+    }
+
+    // push any neighbors that may change onto our propagating set
+    fn push_neighbors(
+        &self,
+        x: usize,
+        y: usize,
+        propagating: &mut Vec<(usize, usize)>,
+    ) {
+        if x > 0 && self.live(x-1, y) {
+            propagating.push((x-1, y));
+        }
+        if y > 0 && self.live(x, y-1) {
+            propagating.push((x, y-1));
+        }
+        if x < self.width-1 && self.live(x+1, y) {
+            propagating.push((x+1, y));
+        }
+        if y < self.height-1 && self.live(x, y+1) {
+            propagating.push((x, y+1));
+        }
+    }
+
+    // find our neighbor in a direction, if it's in our window
+    fn neighbor(
+        &self,
+        x: usize,
+        y: usize,
+        dir: Dir,
+    ) -> Option<(usize, usize)> {
+        match dir {
+            Dir::N if y > 0             => Some((x, y-1)),
+            Dir::E if x < self.width-1  => Some((x+1, y)),
+            Dir::S if y < self.height-1 => Some((x, y+1)),
+            Dir::W if x > 0             => Some((x-1, y)),
+            _ => None,
+        }
+    }
+
+    // note that a cell changed from c to c_, queueing up any work for our
+    // propagator
+    fn changed(
+        &self,
+        x: usize,
+        y: usize,
+        c: u128,
+        c_: u128,
+        pending: &mut Pending,
+    ) {
+        match self.propagator {
+            Propagator::Naive => {
+                self.push_neighbors(x, y, &mut pending.cells);
+            }
+            Propagator::Ac4 => {
+                let mut removed = c & !c_;
+                while removed != 0 {
+                    let t = removed.trailing_zeros() as usize;
+                    removed &= removed - 1;
+                    pending.removals.push((x+y*self.width, t));
+                }
+            }
+        }
+    }
+
+    // reset any changed cells around a contradiction back to their initial
+    // possibilities, so we can re-solve just that neighborhood while
+    // keeping the rest of the map fixed
+    //
+    // unlike backtracking, this is spatial rather than chronological,
+    // which suits our mostly-local constraints
+    #[allow(clippy::too_many_arguments)]
+    fn reset_around(
+        &self,
+        (x, y): (usize, usize),
+        radius: usize,
+        init_cmap: &[u128],
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+    ) {
+        for y_ in y.saturating_sub(radius)
+            ..= cmp::min(y+radius, self.height-1)
+        {
+            for x_ in x.saturating_sub(radius)
+                ..= cmp::min(x+radius, self.width-1)
+            {
+                let i = x_+y_*self.width;
+                if !self.live(x_, y_) || cmap[i] == init_cmap[i] {
+                    continue;
+                }
+
+                // note our contradiction has already left its bucket
+                unresolved.remove(cmap[i].count_ones(), x_, y_);
+                trail.set(cmap, i, init_cmap[i]);
+                if init_cmap[i].count_ones() > 1 {
+                    unresolved.insert(init_cmap[i].count_ones(), x_, y_);
+                }
+                match self.propagator {
+                    Propagator::Naive => pending.cells.push((x_, y_)),
+                    // supports only ever go down, so we need to recount
+                    // this cell and the ring around it
+                    Propagator::Ac4 => {
+                        pending.stale.push((x_, y_));
+                        self.push_neighbors(x_, y_, &mut pending.stale);
+                    }
+                }
+            }
+        }
+    }
+
+    // propagate constraints by recomputing each pending cell from all of
+    // its neighbors
+    fn propagate_naive(
+        &self,
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+        stats: &mut WfcStats,
+    ) -> Propagated {
+        while let Some((x, y)) = pending.cells.pop() {
+            // out of budget? stop here with a partial result
+            if self.budget.exceeded(stats.propagations) {
+                return Propagated::Exhausted;
+            }
+            stats.propagations += 1;
+            let mut c = cmap[x+y*self.width];
+
+            // for each neighbor
+            let mut constrain = |x_: usize, y_: usize, dir: Dir| {
+                let c_ = cmap[x_+y_*self.width];
+
+                // what does our neighbor allow us to be?
+                let mut mask = 0;
+                for (i, tile) in TILES.iter().enumerate() {
+                    if c_ & (1 << i) != 0 {
+                        mask |= tile.constraints.dir(dir.flip());
+                    }
+                }
+                c &= mask;
+
+                // does any of our possibilities contradict our neighbor?
+                for (i, tile) in TILES.iter().enumerate() {
+                    if
+                        c & (1 << i) != 0
+                            && tile.constraints.dir(dir) & c_ == 0
+                    {
+                        c &= !(1 << i);
+                    }
+                }
+            };
+
+            if x > 0 { constrain(x-1, y, Dir::W); }
+            if y > 0 { constrain(x, y-1, Dir::N); }
+            if x < self.width-1 { constrain(x+1, y, Dir::E); }
+            if y < self.height-1 { constrain(x, y+1, Dir::S); }
+
+            // did we actually change anything?
+            if cmap[x+y*self.width] != c {
+                // update our map
+                let count = cmap[x+y*self.width].count_ones();
+                let count_ = c.count_ones();
+                trail.set(cmap, x+y*self.width, c);
+                // move into different bucket, note a contradiction leaves
+                // us in no bucket until we're repaired
+                unresolved.remove(count, x, y);
+                if c == 0 {
+                    return Propagated::Contradiction(x, y);
+                }
+                unresolved.insert(count_, x, y);
+                // propagate constraints to our neighbors
+                self.push_neighbors(x, y, &mut pending.cells);
+            }
+        }
+
+        Propagated::Done
+    }
+
+    // remove tiles from a cell, keeping our unresolved set up to date
+    fn remove_tiles(
+        &self,
+        (x, y): (usize, usize),
+        c_: u128,
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+    ) -> Option<Propagated> {
+        let c = cmap[x+y*self.width];
+        if c == c_ {
+            return None;
+        }
+
+        trail.set(cmap, x+y*self.width, c_);
+        // move into different bucket, note a contradiction leaves us in no
+        // bucket until we're repaired
+        unresolved.remove(c.count_ones(), x, y);
+        if c_ == 0 {
+            return Some(Propagated::Contradiction(x, y));
+        }
+        unresolved.insert(c_.count_ones(), x, y);
+        // propagate constraints to our neighbors
+        self.changed(x, y, c, c_, pending);
+        None
+    }
+
+    // count supports for every tile in our stale cells from scratch,
+    // removing any tiles that are already unsupported
+    fn count_supports(
+        &self,
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+    ) -> Option<Propagated> {
+        // any removals we haven't propagated yet leave their neighbors'
+        // supports out of date, so just recount those neighbors too
+        let mut stale = std::mem::take(&mut pending.stale);
+        for (i, _) in pending.removals.drain(..) {
+            self.push_neighbors(i % self.width, i / self.width, &mut stale);
+        }
+        stale.sort_by_key(|&(x, y)| x+y*self.width);
+        stale.dedup();
+
+        for &(x, y) in &stale {
+            let c = cmap[x+y*self.width];
+            for (d, &dir) in DIRS.iter().enumerate() {
+                let (x_, y_) = match self.neighbor(x, y, dir) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                let c_ = cmap[x_+y_*self.width];
+                let mut tiles = c;
+                while tiles != 0 {
+                    let t = tiles.trailing_zeros() as usize;
+                    tiles &= tiles - 1;
+                    pending.supports[
+                        ((x+y*self.width)*4 + d)*TILES.len() + t
+                    ] = (c_ & pending.compat[t][d]).count_ones() as u8;
+                }
+            }
+        }
+
+        // remove anything unsupported, only after counting so our supports
+        // account for everything we queue up
+        for (j, &(x, y)) in stale.iter().enumerate() {
+            if !self.live(x, y) {
+                continue;
+            }
+
+            let mut c_ = cmap[x+y*self.width];
+            for (d, &dir) in DIRS.iter().enumerate() {
+                if self.neighbor(x, y, dir).is_none() {
+                    continue;
+                }
+                let mut tiles = c_;
+                while tiles != 0 {
+                    let t = tiles.trailing_zeros() as usize;
+                    tiles &= tiles - 1;
+                    if pending.supports[
+                        ((x+y*self.width)*4 + d)*TILES.len() + t
+                    ] == 0 {
+                        c_ &= !(1 << t);
+                    }
+                }
+            }
+
+            if let Some(propagated) = self.remove_tiles(
+                (x, y), c_,
+                cmap, trail, unresolved, pending,
+            ) {
+                // anything we didn't get to still needs checking
+                pending.stale.extend_from_slice(&stale[j+1..]);
+                return Some(propagated);
+            }
+        }
+
+        None
+    }
+
+    // propagate constraints by decrementing the supports of any tiles
+    // our removed tiles supported, removing tiles whose supports hit zero
+    fn propagate_ac4(
+        &self,
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+        stats: &mut WfcStats,
+    ) -> Propagated {
+        if !pending.stale.is_empty() {
+            if let Some(propagated) = self.count_supports(
+                cmap, trail, unresolved, pending,
+            ) {
+                return propagated;
+            }
+        }
+
+        while let Some((i, t)) = pending.removals.pop() {
+            // out of budget? stop here with a partial result
+            if self.budget.exceeded(stats.propagations) {
+                return Propagated::Exhausted;
+            }
+            stats.propagations += 1;
+            let (x, y) = (i % self.width, i / self.width);
+
+            // for each neighbor
+            for (d, &dir) in DIRS.iter().enumerate() {
+                let (x_, y_) = match self.neighbor(x, y, dir) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                let i_ = x_+y_*self.width;
+
+                // every tile we supported loses a support
+                let mut c_ = cmap[i_];
+                let mut supported = c_ & pending.compat[t][d];
+                while supported != 0 {
+                    let t_ = supported.trailing_zeros() as usize;
+                    supported &= supported - 1;
+                    let support = &mut pending.supports[
+                        (i_*4 + (d+2)%4)*TILES.len() + t_
+                    ];
+                    *support -= 1;
+                    if *support == 0 && self.live(x_, y_) {
+                        c_ &= !(1 << t_);
+                    }
+                }
+
+                if let Some(propagated) = self.remove_tiles(
+                    (x_, y_), c_,
+                    cmap, trail, unresolved, pending,
+                ) {
+                    return propagated;
+                }
+            }
+        }
+
+        Propagated::Done
+    }
+
+    fn propagate(
+        &self,
+        cmap: &mut [u128],
+        trail: &mut Trail,
+        unresolved: &mut ConstraintSet,
+        pending: &mut Pending,
+        stats: &mut WfcStats,
+    ) -> Propagated {
+        match self.propagator {
+            Propagator::Naive => self.propagate_naive(
+                cmap, trail, unresolved, pending, stats,
+            ),
+            Propagator::Ac4 => self.propagate_ac4(
+                cmap, trail, unresolved, pending, stats,
+            ),
+        }
+    }
+
+    // evaluate any unresolved constraints in cmap, returns false if we
+    // couldn't find a solution in our attempts
+    //
+    // anim is called after each propagation, this is really just for fun
+    pub fn solve(
+        &self,
+        cmap: &mut [u128],
+        prng: &mut Xorshift64,
+        stats: &mut WfcStats,
+        anim: Anim<'_>,
+    ) -> bool {
+        // we need our initial constraint map to repair contradictions
+        let init_cmap = if self.repair > 0 {
+            cmap.to_vec()
+        } else {
+            vec![]
+        };
+        self.solve_from(cmap, &init_cmap, &mut Trail::new(), prng, stats, anim)
+    }
+
+    // same as solve, but repair contradictions by resetting cells back to
+    // init_cmap, which may be less constrained than cmap
+    //
+    // any changes we make are logged to trail, which allows us to quickly
+    // revert failed attempts, and lets callers roll back our changes
+    pub fn solve_from(
+        &self,
+        cmap: &mut [u128],
+        init_cmap: &[u128],
+        trail: &mut Trail,
+        prng: &mut Xorshift64,
+        stats: &mut WfcStats,
+        mut anim: Anim<'_>,
+    ) -> bool {
+        let checkpoint = trail.checkpoint();
+
+        // figure out what we actually need to resolve
+        let mut init_unresolved: Vec<(usize, usize)> = vec![];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = cmap[x+y*self.width];
+                if c.count_ones() > 1 && self.live(x, y) {
+                    init_unresolved.push((x, y));
+                }
+            }
+        }
+        let init_unresolved = init_unresolved;
+
+        let mut pending = Pending{
+            cells: vec![],
+            removals: vec![],
+            supports: match self.propagator {
+                Propagator::Naive => vec![],
+                Propagator::Ac4 => vec![
+                    0; self.width*self.height*4*TILES.len()
+                ],
+            },
+            compat: match self.propagator {
+                Propagator::Naive => vec![],
+                Propagator::Ac4 => tile_supports(),
+            },
+            stale: vec![],
+        };
+
+        let mut attempts = 0;
+        let mut success = false;
+        'wfc: for _ in 0..self.attempts {
+            if self.budget.exceeded(stats.propagations) {
+                stats.exhausted = true;
+                break;
+            }
+            attempts += 1;
+
+            // reset to initial constraint map
+            trail.rollback(cmap, checkpoint);
+
+            // keep track of all unresolved constraints
+            let mut unresolved: ConstraintSet = ConstraintSet::new();
+
+            match self.propagator {
+                // add all unresolved to our propagating set, these will be
+                // moved into the unresolved tree after constraints are
+                // evaluated
+                Propagator::Naive => {
+                    pending.cells = init_unresolved.clone();
+                }
+                // AC-4 needs to count supports before it can propagate
+                // anything
+                Propagator::Ac4 => {
+                    for &(x, y) in &init_unresolved {
+                        unresolved.insert(
+                            cmap[x+y*self.width].count_ones(), x, y
+                        );
+                    }
+                    pending.stale = (0..self.height)
+                        .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+                        .collect();
+                }
+            }
+            let mut repairs = 0;
+
+            // core wfc algorithm
+            loop {
+                stats.cycles += 1;
+
+                // propagate new constraints
+                loop {
+                    match self.propagate(
+                        cmap, trail, &mut unresolved, &mut pending,
+                        stats,
+                    ) {
+                        Propagated::Done => break,
+                        Propagated::Exhausted => {
+                            stats.exhausted = true;
+                            break 'wfc;
+                        }
+                        // contradiction? try to repair it by re-solving
+                        // a growing neighborhood, otherwise abort the
+                        // current wfc
+                        Propagated::Contradiction(x, y) => {
+                            if repairs >= self.repair {
+                                pending.cells.clear();
+                                pending.removals.clear();
+                                continue 'wfc;
+                            }
+                            repairs += 1;
+                            stats.repairs += 1;
+                            self.reset_around(
+                                (x, y), repairs,
+                                init_cmap, cmap, trail,
+                                &mut unresolved, &mut pending,
+                            );
+                        }
+                    }
+                }
+
+                // animate wfc?
+                if let Some(anim) = anim.as_mut() {
+                    anim(cmap);
+                }
+
+                // do we have unresolved constraints? choose the most-resolved
+                while pending.is_empty() {
+                    match unresolved.pop(prng) {
+                        Some((_, x, y)) => {
+                            // randomly assign it to one of its options
+                            let c_ = cmap[x+y*self.width];
+                            let mut c = c_;
+                            debug_assert!(c.count_ones() > 0);
+                            if c.count_ones() > 1 {
+                                let choice = match self.zmap[x+y*self.width] {
+                                    // zones may weight tiles differently,
+                                    // note we count from the highest bit
+                                    Some(zone) => {
+                                        let weights = (0..TILES.len()).rev()
+                                            .filter(|&i| c & (1 << i) != 0)
+                                            .map(|i| zone.weight(1 << i))
+                                            .collect::<Vec<_>>();
+                                        let mut p = prng.next()
+                                            * weights.iter().sum::<f64>();
+                                        let mut choice = 0;
+                                        while
+                                            choice < weights.len()-1
+                                                && p >= weights[choice]
+                                        {
+                                            p -= weights[choice];
+                                            choice += 1;
+                                        }
+                                        choice
+                                    }
+                                    None => prng.range(
+                                        0..c.count_ones() as usize
+                                    ),
+                                };
+                                // figure out which bit this actually is, kinda
+                                // complicated
+                                for _ in 0..choice {
+                                    c &= !(1 << (128-1-c.leading_zeros()));
+                                }
+                                c &= !((1 << (128-1-c.leading_zeros()))-1);
+
+                                // update our map
+                                trail.set(cmap, x+y*self.width, c);
+                                // propagate constraints to our neighbors
+                                self.changed(x, y, c_, c, &mut pending);
+                            }
+                        }
+                        None => {
+                            success = true;
+                            break 'wfc;
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.attempts = cmp::max(stats.attempts, attempts);
+        success
+    }
+
+    // solve our window in independent regions on worker threads, one
+    // region per bubble, before reconciling any seams between regions
+    //
+    // bubbles are given as x, y, r in tiles relative to our window, cells
+    // belong to whichever bubble's edge is closest, which puts seams near
+    // the middle of hallways where propagation rarely needs to cross
+    //
+    // each region gets its own seed up front, so our results only depend
+    // on our prng and not on the number of threads
+    pub fn solve_regions(
+        &self,
+        cmap: &mut [u128],
+        bubbles: &[(isize, isize, usize)],
+        threads: usize,
+        prng: &mut Xorshift64,
+        stats: &mut WfcStats,
+        anim: Anim<'_>,
+    ) -> bool {
+        if bubbles.is_empty() {
+            return self.solve(cmap, prng, stats, anim);
+        }
+
+        // assign unresolved cells to regions
+        let init_cmap = cmap.to_vec();
+        let mut region = vec![None; self.width*self.height];
+        let mut bounds = vec![None; bubbles.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if cmap[x+y*self.width].count_ones() <= 1 || !self.live(x, y) {
+                    continue;
+                }
+
+                let (i, _) = bubbles.iter().enumerate()
+                    .map(|(i, &(x_, y_, r))| (
+                        i,
+                        dist((x as isize, y as isize), (x_, y_)) - r as f64
+                    ))
+                    .fold((0, f64::INFINITY), |a, b| {
+                        if b.1 < a.1 { b } else { a }
+                    });
+                region[x+y*self.width] = Some(i);
+                let (lower_x, lower_y, upper_x, upper_y)
+                    = bounds[i].unwrap_or((x, y, x, y));
+                bounds[i] = Some((
+                    cmp::min(lower_x, x),
+                    cmp::min(lower_y, y),
+                    cmp::max(upper_x, x),
+                    cmp::max(upper_y, y),
+                ));
+            }
+        }
+
+        // split whatever's left of our propagation budget between regions
+        // by size, a shared counter would make results depend on how our
+        // threads are scheduled
+        let cells = region.iter().filter(|region| region.is_some()).count();
+        let mut sizes = vec![0; bubbles.len()];
+        for &i in region.iter().flatten() {
+            sizes[i] += 1;
+        }
+        let remaining = self.budget.propagations.map(|limit| {
+            limit.saturating_sub(stats.propagations)
+        });
+
+        // give each region a seed, a window, including a border of fixed
+        // neighbors, and a budget
+        let regions = bounds.iter().enumerate()
+            .filter_map(|(i, bounds)| bounds.map(|bounds| (i, bounds)))
+            .map(|(i, (lower_x, lower_y, upper_x, upper_y))| {
+                let lower_x = lower_x.saturating_sub(1);
+                let lower_y = lower_y.saturating_sub(1);
+                let upper_x = cmp::min(upper_x+1, self.width-1);
+                let upper_y = cmp::min(upper_y+1, self.height-1);
+                (
+                    i,
+                    (lower_x, lower_y, upper_x+1-lower_x, upper_y+1-lower_y),
+                    prng.next_u64(),
+                    Budget{
+                        propagations: remaining.map(|remaining| {
+                            remaining * sizes[i] as u64 / cells as u64
+                        }),
+                        ..self.budget.clone()
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // solve each region, each thread takes the next region until we
+        // run out
+        let threads = threads.clamp(1, cmp::max(regions.len(), 1));
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; regions.len()]);
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    let j = next.fetch_add(1, Ordering::Relaxed);
+                    let (i, (x, y, width, height), seed, budget)
+                        = match regions.get(j) {
+                            Some(region) => region.clone(),
+                            None => return,
+                        };
+
+                    let mut cmap_ = vec![0; width*height];
+                    let mut zmap_ = vec![None; width*height];
+                    let mut live_ = vec![false; width*height];
+                    for y_ in 0..height {
+                        for x_ in 0..width {
+                            let k = (x+x_) + (y+y_)*self.width;
+                            cmap_[x_+y_*width] = init_cmap[k];
+                            zmap_[x_+y_*width] = self.zmap[k];
+                            live_[x_+y_*width] = region[k] == Some(i);
+                        }
+                    }
+
+                    let wfc = Wfc{
+                        width,
+                        height,
+                        zmap: &zmap_,
+                        live: Some(&live_),
+                        attempts: self.attempts,
+                        budget: &budget,
+                        repair: self.repair,
+                        propagator: self.propagator,
+                    };
+                    let mut stats_ = WfcStats::default();
+                    // xorshift gets stuck at zero
+                    let mut prng_ = Xorshift64(cmp::max(seed, 1));
+                    let success = wfc.solve(
+                        &mut cmap_,
+                        &mut prng_,
+                        &mut stats_,
+                        None,
+                    );
+                    results.lock().unwrap()[j] = Some((cmap_, stats_, success));
+                });
+            }
+        });
+
+        // write back any solved regions, failed regions stay as they were
+        let mut dirty = vec![false; self.width*self.height];
+        for (&(i, (x, y, width, height), _, _), result) in regions.iter()
+            .zip(results.into_inner().unwrap())
+        {
+            let (cmap_, stats_, success) = result.unwrap();
+            stats.attempts = cmp::max(stats.attempts, stats_.attempts);
+            stats.cycles += stats_.cycles;
+            stats.propagations += stats_.propagations;
+            stats.repairs += stats_.repairs;
+            stats.exhausted |= stats_.exhausted;
+
+            for y_ in 0..height {
+                for x_ in 0..width {
+                    let k = (x+x_) + (y+y_)*self.width;
+                    if region[k] == Some(i) {
+                        // keep partial results if we ran out of budget
+                        if success || stats_.exhausted {
+                            cmap[k] = cmap_[x_+y_*width];
+                        } else {
+                            dirty[k] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        // find any conflicts where regions meet
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let k = x+y*self.width;
+                if region[k].is_none() {
+                    continue;
+                }
+
+                if
+                    x < self.width-1
+                        && region[k+1].is_some()
+                        && region[k+1] != region[k]
+                        && !compatible(cmap[k], cmap[k+1], Dir::E)
+                {
+                    dirty[k] = true;
+                    dirty[k+1] = true;
+                }
+                if
+                    y < self.height-1
+                        && region[k+self.width].is_some()
+                        && region[k+self.width] != region[k]
+                        && !compatible(cmap[k], cmap[k+self.width], Dir::S)
+                {
+                    dirty[k] = true;
+                    dirty[k+self.width] = true;
+                }
+            }
+        }
+
+        // out of budget? don't bother with seams
+        if stats.exhausted {
+            return false;
+        }
+
+        if !dirty.contains(&true) {
+            return true;
+        }
+
+        // reset any cells near conflicts and re-solve just those
+        let mut seam = vec![false; self.width*self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !dirty[x+y*self.width] {
+                    continue;
+                }
+
+                for y_ in y.saturating_sub(SEAM_MARGIN)
+                    ..= cmp::min(y+SEAM_MARGIN, self.height-1)
+                {
+                    for x_ in x.saturating_sub(SEAM_MARGIN)
+                        ..= cmp::min(x+SEAM_MARGIN, self.width-1)
+                    {
+                        let k = x_+y_*self.width;
+                        if region[k].is_some() {
+                            seam[k] = true;
+                            cmap[k] = init_cmap[k];
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut anim = anim;
+        let wfc = Wfc{
+            width: self.width,
+            height: self.height,
+            zmap: self.zmap,
+            live: Some(&seam),
+            attempts: self.attempts,
+            budget: self.budget,
+            repair: self.repair,
+            propagator: self.propagator,
+        };
+        let reanim = anim.as_mut()
+            .map(|anim| &mut **anim as &mut dyn FnMut(&[u128]));
+        if wfc.solve(cmap, prng, stats, reanim) || stats.exhausted {
+            return !stats.exhausted;
+        }
+
+        // still stuck? fall back to solving all regions at once
+        let all = region.iter()
+            .map(|region| region.is_some())
+            .collect::<Vec<_>>();
+        for k in 0..cmap.len() {
+            if all[k] {
+                cmap[k] = init_cmap[k];
+            }
+        }
+        let wfc = Wfc{
+            width: self.width,
+            height: self.height,
+            zmap: self.zmap,
+            live: Some(&all),
+            attempts: self.attempts,
+            budget: self.budget,
+            repair: self.repair,
+            propagator: self.propagator,
+        };
+        wfc.solve(cmap, prng, stats, anim)
+    }
+}
@@ -0,0 +1,87 @@
+use serde::Serialize;
+
+use std::str::FromStr;
+
+use crate::constraints::*;
+
+
+// zones give different parts of the station a different feel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all="kebab-case")]
+pub enum Zone {
+    Habitation,
+    Engineering,
+    Hydroponics,
+    Cargo,
+}
+
+impl Zone {
+    pub const ALL: [Zone; 4] = [
+        Zone::Habitation,
+        Zone::Engineering,
+        Zone::Hydroponics,
+        Zone::Cargo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Zone::Habitation  => "habitation",
+            Zone::Engineering => "engineering",
+            Zone::Hydroponics => "hydroponics",
+            Zone::Cargo       => "cargo",
+        }
+    }
+
+    // how we show this zone in bubble maps
+    pub fn ascii(self) -> u8 {
+        match self {
+            Zone::Habitation  => b'.',
+            Zone::Engineering => b'=',
+            Zone::Hydroponics => b'"',
+            Zone::Cargo       => b'#',
+        }
+    }
+
+    // which tiles are allowed in this zone
+    pub fn tiles(self) -> u128 {
+        match self {
+            // cargo bays are big open spaces
+            Zone::Cargo => TILE_ALL & !TILE_INWALLISH,
+            _ => TILE_ALL,
+        }
+    }
+
+    // relative weight of each tile when collapsing
+    pub fn weight(self, tile: u128) -> f64 {
+        match self {
+            // lots of little rooms
+            Zone::Habitation if tile & TILE_INWALLISH != 0 => 4.0,
+            // lots of doors between machinery
+            Zone::Engineering if tile & TILE_DOORISH != 0 => 4.0,
+            // long open greenhouses
+            Zone::Hydroponics if tile & TILE_INWALLISH != 0 => 0.25,
+            _ => 1.0,
+        }
+    }
+}
+
+// how should we assign zones to bubbles?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneMode {
+    // zones form rings based on distance from the root bubble
+    Depth,
+    // zones form clusters of bubbles grown from the same parent
+    Cluster,
+}
+
+impl FromStr for ZoneMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth"   => Ok(ZoneMode::Depth),
+            "cluster" => Ok(ZoneMode::Cluster),
+            _ => Err(format!("unknown zone mode {:?}", s)),
+        }
+    }
+}